@@ -1,6 +1,9 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+#[cfg(feature = "hash-comparison")]
 use sha2::{Digest, Sha256};
-use stacksat128::stacksat_hash;
+use stacksat128::{
+    stacksat_hash, stacksat_hash_batch_with_threshold, Stacksat128, DEFAULT_MIN_PARALLEL,
+};
 
 const KB: usize = 1024;
 
@@ -10,47 +13,119 @@ fn generate_data(size: usize) -> Vec<u8> {
 }
 
 fn hashing_benchmarks(c: &mut Criterion) {
-    let data_1k = generate_data(KB);
-    let data_64k = generate_data(64 * KB);
-
     let mut group = c.benchmark_group("Hashing Algorithms Comparison");
 
-    // --- Benchmarks for 1KB Input ---
-    group.bench_with_input("STACKSAT-128 (1KB)", &data_1k, |b, data| {
-        b.iter(|| stacksat_hash(black_box(data)))
-    });
+    for (label, size) in [("1KB", KB), ("64KB", 64 * KB)] {
+        let data = generate_data(size);
+        // Report MB/s so results compare across input sizes
+        group.throughput(Throughput::Bytes(size as u64));
 
-    group.bench_with_input("SHA-256 (1KB)", &data_1k, |b, data| {
-        b.iter(|| {
-            let mut hasher = Sha256::new();
-            hasher.update(black_box(data));
-            hasher.finalize()
-        })
-    });
-
-    group.bench_with_input("BLAKE3 (1KB)", &data_1k, |b, data| {
-        b.iter(|| blake3::hash(black_box(data)))
-    });
-
-    // --- Benchmarks for 64KB Input ---
-    group.bench_with_input("STACKSAT-128 (64KB)", &data_64k, |b, data| {
-        b.iter(|| stacksat_hash(black_box(data)))
-    });
-
-    group.bench_with_input("SHA-256 (64KB)", &data_64k, |b, data| {
-        b.iter(|| {
-            let mut hasher = Sha256::new();
-            hasher.update(black_box(data));
-            hasher.finalize()
+        group.bench_with_input(format!("STACKSAT-128 ({})", label), &data, |b, data| {
+            b.iter(|| stacksat_hash(black_box(data)))
+        });
+
+        #[cfg(feature = "hash-comparison")]
+        group.bench_with_input(format!("SHA-256 ({})", label), &data, |b, data| {
+            b.iter(|| {
+                let mut hasher = Sha256::new();
+                hasher.update(black_box(data));
+                hasher.finalize()
+            })
+        });
+
+        #[cfg(feature = "hash-comparison")]
+        group.bench_with_input(format!("BLAKE3 ({})", label), &data, |b, data| {
+            b.iter(|| blake3::hash(black_box(data)))
+        });
+    }
+
+    group.finish();
+}
+
+const STREAM_CHUNK: usize = 256;
+
+// Feed `data` to the streaming hasher in STREAM_CHUNK-byte updates
+fn stream_hash(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Stacksat128::new();
+    for chunk in data.chunks(STREAM_CHUNK) {
+        hasher.update(chunk);
+    }
+    hasher.finalize()
+}
+
+fn streaming_benchmarks(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Streaming vs One-shot");
+
+    for (label, size) in [("1KB", KB), ("64KB", 64 * KB)] {
+        let data = generate_data(size);
+        assert_eq!(
+            stream_hash(&data),
+            stacksat_hash(&data),
+            "streaming and one-shot digests differ"
+        );
+        group.throughput(Throughput::Bytes(size as u64));
+
+        group.bench_with_input(format!("One-shot ({})", label), &data, |b, data| {
+            b.iter(|| stacksat_hash(black_box(data)))
+        });
+
+        group.bench_with_input(
+            format!("Streaming, {}-byte updates ({})", STREAM_CHUNK, label),
+            &data,
+            |b, data| b.iter(|| stream_hash(black_box(data))),
+        );
+    }
+
+    group.finish();
+}
+
+// 32-byte messages, each distinct in its first four bytes
+fn generate_batch(count: usize) -> Vec<[u8; 32]> {
+    (0..count as u32)
+        .map(|i| {
+            let mut msg = [0u8; 32];
+            msg[..4].copy_from_slice(&i.to_be_bytes());
+            msg
         })
-    });
+        .collect()
+}
+
+// Serial against parallel hashing on both sides of DEFAULT_MIN_PARALLEL. The
+// threshold is worth moving when the crossover lands far from it.
+fn batch_benchmarks(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Batch Serial vs Parallel");
+
+    for count in [
+        DEFAULT_MIN_PARALLEL / 4,
+        DEFAULT_MIN_PARALLEL,
+        DEFAULT_MIN_PARALLEL * 4,
+    ] {
+        let batch = generate_batch(count);
+        let messages: Vec<&[u8]> = batch.iter().map(|msg| &msg[..]).collect();
+        group.throughput(Throughput::Elements(count as u64));
+
+        group.bench_with_input(
+            format!("Serial ({} messages)", count),
+            &messages,
+            |b, messages| {
+                b.iter(|| stacksat_hash_batch_with_threshold(black_box(messages), usize::MAX))
+            },
+        );
 
-    group.bench_with_input("BLAKE3 (64KB)", &data_64k, |b, data| {
-        b.iter(|| blake3::hash(black_box(data)))
-    });
+        group.bench_with_input(
+            format!("Parallel ({} messages)", count),
+            &messages,
+            |b, messages| b.iter(|| stacksat_hash_batch_with_threshold(black_box(messages), 0)),
+        );
+    }
 
     group.finish();
 }
 
-criterion_group!(benches, hashing_benchmarks);
+criterion_group!(
+    benches,
+    hashing_benchmarks,
+    streaming_benchmarks,
+    batch_benchmarks
+);
 criterion_main!(benches);
@@ -0,0 +1,48 @@
+//! Panic-freedom check for the `no_std` hashing path.
+//!
+//! Exports the one-shot and streaming hash through a C ABI from a `no_std`
+//! cdylib. Built without `std` and with `panic = "abort"`, any panic reachable
+//! from the exports shows up as a call into `core::panicking` in the library:
+//!
+//! ```text
+//! cargo rustc -p stacksat128 --release --example no_std_panic_free \
+//!     --no-default-features -- -C panic=abort
+//! objdump -d --no-show-raw-insn target/release/examples/libno_std_panic_free.so \
+//!     | awk '/^[0-9a-f]+ <.*>:$/ { fn = $2 } /call|jmp/ && /panicking/ && fn ~ /stacksat/'
+//! ```
+//!
+//! The second command must print nothing. The check only holds with
+//! `--release`: debug builds cross-check the lane absorption with a panicking
+//! assertion.
+#![no_std]
+
+#[cfg(not(feature = "std"))]
+#[panic_handler]
+fn panic(_: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
+
+/// # Safety
+/// `msg` must be valid for `len` bytes and `out` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn stacksat_hash_c(msg: *const u8, len: usize, out: *mut [u8; 32]) {
+    let msg = core::slice::from_raw_parts(msg, len);
+    *out = stacksat128::stacksat_hash(msg);
+}
+
+/// # Safety
+/// `a` and `b` must be valid for `a_len` and `b_len` bytes and `out` valid for
+/// writes.
+#[no_mangle]
+pub unsafe extern "C" fn stacksat_stream_c(
+    a: *const u8,
+    a_len: usize,
+    b: *const u8,
+    b_len: usize,
+    out: *mut [u8; 32],
+) {
+    let mut hasher = stacksat128::Stacksat128::new();
+    hasher.update(core::slice::from_raw_parts(a, a_len));
+    hasher.update(core::slice::from_raw_parts(b, b_len));
+    *out = hasher.finalize();
+}
@@ -0,0 +1,70 @@
+//! Prints the test vectors as JSON.
+//!
+//! ```text
+//! cargo run -p stacksat128 --example test_vectors > vectors.json
+//! cargo run -p stacksat128 --example test_vectors -- --trace > vectors_trace.json
+//! ```
+//!
+//! With `--trace`, the first `TRACED_VECTORS` entries also carry every
+//! intermediate state from `stacksat_hash_trace`: per block, the state after
+//! absorbing it and after each round, as 64 hex nibbles in stack order (nibble
+//! 0 deepest). Script implementers can diff their stack against it round by
+//! round. The last traced state is checked against the digest before printing.
+
+use stacksat128::{stacksat_hash, stacksat_hash_trace, TEST_VECTORS};
+
+/// Vectors that get a trace with `--trace`: the empty message, `"abc"` and
+/// the three-block pangram.
+const TRACED_VECTORS: usize = 3;
+
+const ROUNDS: usize = 16;
+
+fn nibbles_hex(state: &[u8; 64]) -> String {
+    state
+        .iter()
+        .map(|nibble| char::from_digit(u32::from(*nibble), 16).unwrap())
+        .collect()
+}
+
+fn main() {
+    let trace = match std::env::args().nth(1).as_deref() {
+        None => false,
+        Some("--trace") => true,
+        Some(arg) => {
+            eprintln!("unknown argument {:?}; usage: test_vectors [--trace]", arg);
+            std::process::exit(2);
+        }
+    };
+
+    println!("[");
+    for (i, (message, digest)) in TEST_VECTORS.iter().enumerate() {
+        assert_eq!(hex::encode(stacksat_hash(message)), *digest);
+        println!("  {{");
+        println!("    \"message\": \"{}\",", hex::encode(message));
+        print!("    \"digest\": \"{}\"", digest);
+        if trace && i < TRACED_VECTORS {
+            let states = stacksat_hash_trace(message);
+            assert_eq!(nibbles_hex(states.last().unwrap()), *digest);
+            println!(",");
+            println!("    \"blocks\": [");
+            let blocks = states.chunks_exact(ROUNDS + 1).collect::<Vec<_>>();
+            for (b, block) in blocks.iter().enumerate() {
+                println!("      {{");
+                println!("        \"absorbed\": \"{}\",", nibbles_hex(&block[0]));
+                println!("        \"rounds\": [");
+                for (r, state) in block[1..].iter().enumerate() {
+                    let sep = if r + 1 < ROUNDS { "," } else { "" };
+                    println!("          \"{}\"{}", nibbles_hex(state), sep);
+                }
+                println!("        ]");
+                let sep = if b + 1 < blocks.len() { "," } else { "" };
+                println!("      }}{}", sep);
+            }
+            print!("    ]");
+        }
+        println!();
+        let sep = if i + 1 < TEST_VECTORS.len() { "," } else { "" };
+        println!("  }}{}", sep);
+    }
+    println!("]");
+}
@@ -0,0 +1,195 @@
+//! Binary Merkle trees with STACKSAT-128 as the node hash.
+//!
+//! Every hash is tagged by its first byte so that leaves, internal nodes and
+//! padding can't be confused for one another:
+//!
+//! - leaf: `H(0x00 || leaf)`
+//! - internal node: `H(0x01 || left || right)`
+//! - empty node: `H(0x02)`
+//!
+//! A level with an odd number of nodes pairs its last node with the empty
+//! node, so `[a, b, c]` and `[a, b, c, c]` have different roots. The root of
+//! no leaves is the empty node; the root of one leaf is its leaf hash.
+
+use crate::{ct_eq, Stacksat128, DIGEST_BYTES};
+
+const LEAF_TAG: u8 = 0x00;
+const NODE_TAG: u8 = 0x01;
+const EMPTY_TAG: u8 = 0x02;
+
+fn tagged_hash(tag: u8, parts: &[&[u8; DIGEST_BYTES]]) -> [u8; DIGEST_BYTES] {
+    let mut hasher = Stacksat128::new();
+    hasher.update(&[tag]);
+    for part in parts {
+        hasher.update(*part);
+    }
+    hasher.finalize()
+}
+
+fn hash_leaf(leaf: &[u8; DIGEST_BYTES]) -> [u8; DIGEST_BYTES] {
+    tagged_hash(LEAF_TAG, &[leaf])
+}
+
+fn hash_node(left: &[u8; DIGEST_BYTES], right: &[u8; DIGEST_BYTES]) -> [u8; DIGEST_BYTES] {
+    tagged_hash(NODE_TAG, &[left, right])
+}
+
+fn empty_node() -> [u8; DIGEST_BYTES] {
+    tagged_hash(EMPTY_TAG, &[])
+}
+
+fn next_level(level: &[[u8; DIGEST_BYTES]]) -> Vec<[u8; DIGEST_BYTES]> {
+    let empty = empty_node();
+    level
+        .chunks(2)
+        .map(|pair| hash_node(&pair[0], pair.get(1).unwrap_or(&empty)))
+        .collect()
+}
+
+/// Root of the tree over `leaves`.
+pub fn merkle_root(leaves: &[[u8; DIGEST_BYTES]]) -> [u8; DIGEST_BYTES] {
+    if leaves.is_empty() {
+        return empty_node();
+    }
+    let mut level: Vec<[u8; DIGEST_BYTES]> = leaves.iter().map(hash_leaf).collect();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level[0]
+}
+
+/// Sibling hashes from leaf `index` up to the root, bottom level first.
+/// Panics if `index` is out of range.
+pub fn merkle_proof(leaves: &[[u8; DIGEST_BYTES]], index: usize) -> Vec<[u8; DIGEST_BYTES]> {
+    assert!(
+        index < leaves.len(),
+        "leaf index {} out of range for {} leaves",
+        index,
+        leaves.len()
+    );
+    let empty = empty_node();
+    let mut level: Vec<[u8; DIGEST_BYTES]> = leaves.iter().map(hash_leaf).collect();
+    let mut index = index;
+    let mut proof = Vec::new();
+    while level.len() > 1 {
+        proof.push(*level.get(index ^ 1).unwrap_or(&empty));
+        level = next_level(&level);
+        index /= 2;
+    }
+    proof
+}
+
+/// Check that `leaf` sits at `index` in the tree with `root`. The proof length
+/// fixes the tree depth, so an `index` that doesn't fit in it is rejected. The
+/// final comparison is constant-time.
+#[must_use = "an ignored verification result checks nothing"]
+pub fn verify_proof(
+    root: &[u8; DIGEST_BYTES],
+    leaf: &[u8; DIGEST_BYTES],
+    index: usize,
+    proof: &[[u8; DIGEST_BYTES]],
+) -> bool {
+    let depth = u32::try_from(proof.len()).unwrap_or(u32::MAX);
+    if index.checked_shr(depth).unwrap_or(0) != 0 {
+        return false;
+    }
+    let mut node = hash_leaf(leaf);
+    let mut index = index;
+    for sibling in proof {
+        node = if index & 1 == 0 {
+            hash_node(&node, sibling)
+        } else {
+            hash_node(sibling, &node)
+        };
+        index >>= 1;
+    }
+    ct_eq(&node, root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves(n: usize) -> Vec<[u8; DIGEST_BYTES]> {
+        (0..n)
+            .map(|i| core::array::from_fn(|j| (i * 31 + j) as u8))
+            .collect()
+    }
+
+    #[test]
+    fn test_merkle_root_small_tree() {
+        let l = leaves(3);
+        let root = merkle_root(&l);
+        assert_eq!(
+            root,
+            hash_node(
+                &hash_node(&hash_leaf(&l[0]), &hash_leaf(&l[1])),
+                &hash_node(&hash_leaf(&l[2]), &empty_node())
+            )
+        );
+        assert_eq!(
+            hex::encode(root),
+            "b8e8025eeda4cc9a5678872c28605e29b992903784d6d6fb1b021b69491a446d"
+        );
+
+        assert_eq!(merkle_root(&[]), empty_node());
+        assert_eq!(merkle_root(&l[..1]), hash_leaf(&l[0]));
+        // The odd node is padded with the empty node, not duplicated
+        let mut duplicated = l.clone();
+        duplicated.push(l[2]);
+        assert_ne!(merkle_root(&duplicated), root);
+        // A leaf can't pose as an internal node
+        let inner = hash_node(&hash_leaf(&l[0]), &hash_leaf(&l[1]));
+        assert_ne!(merkle_root(&[inner]), merkle_root(&l[..2]));
+    }
+
+    #[test]
+    fn test_merkle_proofs() {
+        for n in 1..=9 {
+            let l = leaves(n);
+            let root = merkle_root(&l);
+            for (index, leaf) in l.iter().enumerate() {
+                let proof = merkle_proof(&l, index);
+                assert_eq!(proof.len(), n.next_power_of_two().trailing_zeros() as usize);
+                assert!(
+                    verify_proof(&root, leaf, index, &proof),
+                    "n {} index {}",
+                    n,
+                    index
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_merkle_tampered_proofs() {
+        let l = leaves(5);
+        let root = merkle_root(&l);
+        let proof = merkle_proof(&l, 2);
+        assert!(verify_proof(&root, &l[2], 2, &proof));
+
+        for i in 0..proof.len() {
+            let mut tampered = proof.clone();
+            tampered[i][0] ^= 1;
+            assert!(!verify_proof(&root, &l[2], 2, &tampered), "sibling {}", i);
+        }
+        let mut wrong_leaf = l[2];
+        wrong_leaf[31] ^= 0x80;
+        assert!(!verify_proof(&root, &wrong_leaf, 2, &proof));
+        assert!(!verify_proof(&root, &l[2], 3, &proof));
+        assert!(!verify_proof(&root, &l[3], 2, &proof));
+        // Indices beyond the proof's depth don't alias smaller ones
+        assert!(!verify_proof(&root, &l[2], 2 + 8, &proof));
+        assert!(!verify_proof(&root, &l[2], usize::MAX, &proof));
+        assert!(!verify_proof(&root, &l[2], 2, &proof[..2]));
+        let mut extended = proof.clone();
+        extended.push(empty_node());
+        assert!(!verify_proof(&root, &l[2], 2, &extended));
+    }
+
+    #[test]
+    #[should_panic(expected = "out of range")]
+    fn test_merkle_proof_index_out_of_range() {
+        merkle_proof(&leaves(3), 3);
+    }
+}
@@ -0,0 +1,109 @@
+//! Sponge padding arithmetic, shared by the reference hash and the script
+//! generators so they can't disagree on block layout.
+//!
+//! A message of `msg_len` bytes is `2 * msg_len` nibbles, zero-padded up to a
+//! multiple of the 32-nibble rate. A message that fills its last block exactly
+//! gets no padding; the empty message is padded to one all-zero block.
+//!
+//! Unlike the 10*1 padding in the specification (§4.1), zero padding is not
+//! injective: a message and the same message with trailing zero bytes that
+//! stay within its last block (or fill the empty message's block) pad alike,
+//! and so hash alike.
+
+use crate::{RATE_BYTES, RATE_NIBBLES};
+
+/// Zero nibbles appended to a `msg_len`-byte message.
+pub fn padding_nibbles(msg_len: usize) -> usize {
+    if msg_len == 0 {
+        RATE_NIBBLES
+    } else {
+        (RATE_BYTES - msg_len % RATE_BYTES) % RATE_BYTES * 2
+    }
+}
+
+/// Nibbles absorbed for a `msg_len`-byte message, padding included. Panics if
+/// the count overflows `usize`.
+pub fn padded_nibble_len(msg_len: usize) -> usize {
+    msg_len
+        .checked_mul(2)
+        .and_then(|nibbles| nibbles.checked_add(padding_nibbles(msg_len)))
+        .expect("STACKSAT-128: message nibble count overflows usize")
+}
+
+/// Rate blocks absorbed for a `msg_len`-byte message.
+pub fn block_count(msg_len: usize) -> usize {
+    padded_nibble_len(msg_len) / RATE_NIBBLES
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(not(feature = "std"))]
+    use std::{vec, vec::Vec};
+
+    #[test]
+    fn test_padding_exhaustive() {
+        for msg_len in 0..256 {
+            // Pad nibble by nibble, as the original reference did
+            let mut nibbles = msg_len * 2;
+            while nibbles == 0 || nibbles % RATE_NIBBLES != 0 {
+                nibbles += 1;
+            }
+
+            assert_eq!(padded_nibble_len(msg_len), nibbles, "msg_len {}", msg_len);
+            assert_eq!(padding_nibbles(msg_len), nibbles - msg_len * 2);
+            assert_eq!(block_count(msg_len), nibbles / RATE_NIBBLES);
+            assert_eq!(block_count(msg_len), msg_len.div_ceil(RATE_BYTES).max(1));
+        }
+    }
+
+    #[test]
+    fn test_padding_collisions() {
+        fn padded(msg: &[u8]) -> Vec<u8> {
+            let mut nibbles: Vec<u8> = msg.iter().flat_map(|b| [b >> 4, b & 0xF]).collect();
+            nibbles.resize(nibbles.len() + padding_nibbles(msg.len()), 0);
+            nibbles
+        }
+        fn trim_zeros(msg: &[u8]) -> &[u8] {
+            let end = msg.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+            &msg[..end]
+        }
+
+        let mut messages = Vec::new();
+        for len in 0..64 {
+            messages.push(vec![0x00; len]);
+            messages.push(vec![0xFF; len]);
+            messages.push((1..=len as u8).collect());
+            // Counter ending in a zero byte, one zero away from the counter above
+            let mut tail_zero: Vec<u8> = (1..=len as u8).collect();
+            if let Some(last) = tail_zero.last_mut() {
+                *last = 0;
+            }
+            messages.push(tail_zero);
+        }
+        messages.sort();
+        messages.dedup();
+
+        // Distinct messages pad alike exactly when they differ only in trailing
+        // zero bytes and span the same number of blocks
+        for (i, a) in messages.iter().enumerate() {
+            for b in messages[i + 1..].iter() {
+                let same_padding = padded(a) == padded(b);
+                let zero_extension =
+                    trim_zeros(a) == trim_zeros(b) && block_count(a.len()) == block_count(b.len());
+                assert_eq!(same_padding, zero_extension, "{:02x?} vs {:02x?}", a, b);
+            }
+        }
+
+        // The known collisions, kept visible until the padding follows the spec
+        assert_eq!(padded(b"a"), padded(b"a\0"));
+        assert_eq!(padded(b""), padded(&[0u8; RATE_BYTES]));
+        assert_ne!(padded(&[0u8; RATE_BYTES]), padded(&[0u8; RATE_BYTES + 1]));
+    }
+
+    #[test]
+    #[should_panic(expected = "overflows usize")]
+    fn test_padded_nibble_len_overflow() {
+        padded_nibble_len(usize::MAX / 2 + 1);
+    }
+}
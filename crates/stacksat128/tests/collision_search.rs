@@ -0,0 +1,68 @@
+// Needs `std`: the builder is only available with the default features.
+#![cfg(feature = "std")]
+
+use std::collections::HashMap;
+
+use stacksat128::StacksatBuilder;
+
+const SAMPLES: u32 = 1 << 18;
+const PREFIX_BYTES: usize = 6; // 48-bit truncated digests
+
+// Count messages whose truncated digest was already produced by an earlier one.
+// The messages are one block each, differing only in a 32-bit counter.
+fn count_prefix_collisions(rounds: usize) -> usize {
+    let builder = StacksatBuilder::new().rounds(rounds);
+    let mut seen = HashMap::with_capacity(SAMPLES as usize);
+    let mut collisions = 0;
+    for i in 0..SAMPLES {
+        let mut msg = [0u8; 16];
+        msg[..4].copy_from_slice(&i.to_be_bytes());
+        let digest = builder.hash32(&msg);
+        let prefix: [u8; PREFIX_BYTES] = digest[..PREFIX_BYTES].try_into().unwrap();
+        if seen.insert(prefix, i).is_some() {
+            collisions += 1;
+        }
+    }
+    collisions
+}
+
+// --- Collision Search Stress Test ---
+
+#[test]
+#[ignore = "long-running; run with `cargo test --release -- --ignored`"]
+fn test_collision_search() {
+    // Birthday bound: expected collisions among n samples of a b-bit value
+    let n = SAMPLES as f64;
+    let expected = n * (n - 1.0) / 2.0 / 2f64.powi(8 * PREFIX_BYTES as i32);
+    println!(
+        "{} samples, {}-bit prefixes: {:.6} collisions expected for a random function",
+        SAMPLES,
+        8 * PREFIX_BYTES,
+        expected
+    );
+
+    // The harness must be able to find collisions: one round barely mixes the
+    // counter into the digest prefix
+    let weak = count_prefix_collisions(1);
+    println!("1 round:   {} collisions", weak);
+    assert!(
+        weak > SAMPLES as usize / 2,
+        "harness found only {} collisions in the 1-round variant",
+        weak
+    );
+
+    // Full rounds are not collision resistant: the column mix cancels some
+    // S-box differences, which gives structural full-digest collisions (see
+    // `test_known_collision` in the crate). Those need a crafted difference
+    // across one column's message nibbles, which no pair of these counter
+    // messages has, so this only checks the generic birthday bound
+    let full = count_prefix_collisions(16);
+    println!("16 rounds: {} collisions", full);
+    assert_eq!(
+        full,
+        0,
+        "{} collisions on {}-bit prefixes",
+        full,
+        8 * PREFIX_BYTES
+    );
+}
@@ -0,0 +1,11 @@
+// Ignoring a digest or a verification result is a compile error under
+// `deny(unused_must_use)`. Regenerate the expected output after a toolchain
+// bump with `TRYBUILD=overwrite cargo test --test must_use`. The cases use
+// `merkle`, which needs `std`.
+#![cfg(feature = "std")]
+
+#[test]
+fn test_must_use_lints_fire() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/*.rs");
+}
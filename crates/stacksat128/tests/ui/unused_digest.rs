@@ -0,0 +1,9 @@
+#![deny(unused_must_use)]
+
+fn main() {
+    let mut hasher = stacksat128::Stacksat128::new();
+    hasher.update(b"abc");
+    stacksat128::stacksat_hash(b"abc");
+    stacksat128::stacksat_digest(b"abc");
+    hasher.finalize();
+}
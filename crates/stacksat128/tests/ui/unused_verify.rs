@@ -0,0 +1,8 @@
+#![deny(unused_must_use)]
+
+fn main() {
+    let expected = stacksat128::stacksat_hash(b"abc");
+    stacksat128::stacksat_verify(b"abc", &expected);
+    stacksat128::stacksat_verify_commit(&expected, &[0u8; 32], b"abc");
+    stacksat128::merkle::verify_proof(&expected, &expected, 0, &[]);
+}
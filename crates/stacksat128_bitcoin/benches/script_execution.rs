@@ -0,0 +1,68 @@
+use std::time::{Duration, Instant};
+
+use bitcoin::ScriptBuf;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use stacksat128::stacksat_hash;
+use stacksat128_bitcoin::{
+    run_script_checked, stacksat128_full_script, stacksat128_push_message_script,
+};
+
+const MESSAGE_LEN: usize = 32;
+
+// Witness plus full hash-check script for `message`, compiled once so the
+// benchmark times only the interpreter
+fn full_script(message: &[u8]) -> ScriptBuf {
+    let mut bytes = stacksat128_push_message_script(message)
+        .compile()
+        .to_bytes();
+    bytes.extend(stacksat128_full_script(message).compile().to_bytes());
+    ScriptBuf::from_bytes(bytes)
+}
+
+// Mean time of `f` over repeated calls lasting at least `budget`
+fn mean_time(budget: Duration, mut f: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    let mut calls = 0u32;
+    while start.elapsed() < budget {
+        f();
+        calls += 1;
+    }
+    start.elapsed() / calls
+}
+
+// Compare the interpreter running the script against the native hash of the
+// same message. Criterion reports each side; the slowdown factor between them
+// is printed up front from a short timing of both.
+fn script_execution_benchmarks(c: &mut Criterion) {
+    let message: Vec<u8> = (0..MESSAGE_LEN as u8).collect();
+    let script = full_script(&message);
+    run_script_checked(script.clone()).expect("hash-check script rejects its message");
+
+    let budget = Duration::from_secs(1);
+    let native = mean_time(budget, || {
+        black_box(stacksat_hash(black_box(&message)));
+    });
+    let executed = mean_time(budget, || {
+        black_box(run_script_checked(script.clone())).unwrap();
+    });
+    println!(
+        "{}-byte message: script execution {:?}, native {:?}, slowdown {:.0}x",
+        MESSAGE_LEN,
+        executed,
+        native,
+        executed.as_secs_f64() / native.as_secs_f64()
+    );
+
+    let mut group = c.benchmark_group("Script Execution vs Native");
+    group.sample_size(10);
+    group.bench_function(format!("Native hash ({} bytes)", MESSAGE_LEN), |b| {
+        b.iter(|| stacksat_hash(black_box(&message)))
+    });
+    group.bench_function(format!("Script execution ({} bytes)", MESSAGE_LEN), |b| {
+        b.iter(|| run_script_checked(script.clone()))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, script_execution_benchmarks);
+criterion_main!(benches);
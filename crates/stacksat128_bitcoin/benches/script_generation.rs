@@ -0,0 +1,45 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use stacksat128_bitcoin::{
+    stacksat128_compute_script_optimized, stacksat128_mixcolumns_positions_script,
+    stacksat128_mixcolumns_script, MAX_MESSAGE_BYTES,
+};
+
+// Compare generation time across changes with criterion baselines:
+// `cargo bench --bench script_generation -- --save-baseline before` on the old
+// tree, then `-- --baseline before` on the new one.
+fn script_generation_benchmarks(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Script Generation");
+
+    // MixColumns throughput is in state positions, compute throughput in
+    // message bytes hashed by the generated script
+    group.throughput(Throughput::Elements(64));
+    group.bench_function("MixColumns (64 positions)", |b| {
+        b.iter(stacksat128_mixcolumns_script)
+    });
+
+    // Generation must be linear in the positions: 16 and 64 positions should
+    // report about the same throughput, where quadratic growth would quarter it
+    for positions in [16usize, 64] {
+        group.throughput(Throughput::Elements(positions as u64));
+        group.bench_function(
+            format!("MixColumns prefix ({} positions)", positions),
+            |b| b.iter(|| stacksat128_mixcolumns_positions_script(positions)),
+        );
+    }
+
+    // The absorption and round scripts are generated once and cloned per
+    // block, so the longest message shows what the cloning costs: per-byte
+    // throughput should stay close to the 64-byte case
+    for message_len in [64usize, MAX_MESSAGE_BYTES] {
+        group.throughput(Throughput::Bytes(message_len as u64));
+        group.bench_function(
+            format!("Compute script ({}-byte message)", message_len),
+            |b| b.iter(|| stacksat128_compute_script_optimized(message_len)),
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, script_generation_benchmarks);
+criterion_main!(benches);
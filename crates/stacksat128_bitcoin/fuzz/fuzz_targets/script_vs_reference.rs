@@ -0,0 +1,45 @@
+//! Runs push + compute + verify for arbitrary messages against `stacksat_hash`.
+//!
+//! ```text
+//! cd crates/stacksat128_bitcoin
+//! cargo +nightly fuzz run script_vs_reference
+//! ```
+//!
+//! `corpus/script_vs_reference` is seeded with inputs at block and chunk
+//! boundaries. Copy failing inputs into `tests/regressions`, where
+//! `test_regression_corpus` checks them on every test run.
+#![no_main]
+
+use bitcoin::ScriptBuf;
+use libfuzzer_sys::fuzz_target;
+use stacksat128_bitcoin::{
+    run_script_checked, stacksat128_compute_script_optimized, stacksat128_push_message_script,
+    stacksat128_verify_output_script, MAX_EXECUTABLE_MESSAGE_BYTES,
+};
+
+fuzz_target!(|data: &[u8]| {
+    let message = &data[..data.len().min(MAX_EXECUTABLE_MESSAGE_BYTES)];
+    let expected = stacksat128::stacksat_hash(message);
+
+    let mut script_bytes = stacksat128_push_message_script(message)
+        .compile()
+        .to_bytes();
+    script_bytes.extend(
+        stacksat128_compute_script_optimized(message.len())
+            .compile()
+            .to_bytes(),
+    );
+    script_bytes.extend(
+        stacksat128_verify_output_script(expected)
+            .compile()
+            .to_bytes(),
+    );
+    if let Err(err) = run_script_checked(ScriptBuf::from_bytes(script_bytes)) {
+        panic!(
+            "script rejects the digest of a {}-byte message {:02x?}: {}",
+            message.len(),
+            message,
+            err
+        );
+    }
+});
@@ -0,0 +1,579 @@
+//! Small tapscript interpreter for the opcodes the STACKSAT-128 scripts use.
+//!
+//! It runs compiled script bytes with no signature checking and no
+//! dependency beyond `std`, so script tests can run without BitVM's
+//! executor. It follows the tapscript rules the generated scripts are held
+//! to: minimal pushes and numbers, minimal `OP_IF` arguments, 4-byte numeric
+//! operands and at most 1,000 elements across both stacks. Anything else, from
+//! signature checks to `OP_CAT`, is reported as unsupported when executed.
+//!
+//! It is a testing aid, not a consensus implementation: a script it accepts
+//! still has to pass `run_script_checked` before going on chain.
+
+/// Most elements the main and alt stacks may hold together.
+pub const MAX_STACK_ELEMENTS: usize = 1000;
+
+// Largest numeric operand, in bytes
+const MAX_NUM_BYTES: usize = 4;
+
+/// Why `execute` or `run_checked` rejected a script. `offset` is the byte
+/// offset of the opcode that failed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExecError {
+    /// An opcode outside the supported set was executed.
+    UnsupportedOpcode { offset: usize, opcode: u8 },
+    /// A push runs past the end of the script.
+    TruncatedPush { offset: usize },
+    /// A push that a shorter opcode could have made.
+    NonMinimalPush { offset: usize },
+    /// An opcode needed more stack elements than there were.
+    StackUnderflow { offset: usize },
+    /// The two stacks together grew past `MAX_STACK_ELEMENTS`.
+    StackOverflow { offset: usize },
+    /// A numeric operand longer than 4 bytes or not minimally encoded.
+    InvalidNumber { offset: usize },
+    /// An `OP_IF` or `OP_NOTIF` argument other than empty or `0x01`.
+    NonMinimalIf { offset: usize },
+    /// `OP_ELSE` or `OP_ENDIF` without `OP_IF`, or `OP_IF` without `OP_ENDIF`.
+    UnbalancedConditional { offset: usize },
+    /// A `*VERIFY` opcode found false.
+    VerifyFailed { offset: usize },
+    /// `OP_RETURN` was executed.
+    OpReturn { offset: usize },
+    /// The script ran to completion but did not leave exactly one true
+    /// element; only returned by `run_checked`.
+    FinalStack { depth: usize },
+}
+
+impl core::fmt::Display for ExecError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ExecError::UnsupportedOpcode { offset, opcode } => {
+                write!(f, "unsupported opcode {:#04x} at offset {}", opcode, offset)
+            }
+            ExecError::TruncatedPush { offset } => {
+                write!(f, "push past the end of the script at offset {}", offset)
+            }
+            ExecError::NonMinimalPush { offset } => {
+                write!(f, "non-minimal push at offset {}", offset)
+            }
+            ExecError::StackUnderflow { offset } => {
+                write!(f, "stack underflow at offset {}", offset)
+            }
+            ExecError::StackOverflow { offset } => write!(
+                f,
+                "more than {} stack elements at offset {}",
+                MAX_STACK_ELEMENTS, offset
+            ),
+            ExecError::InvalidNumber { offset } => {
+                write!(f, "invalid numeric operand at offset {}", offset)
+            }
+            ExecError::NonMinimalIf { offset } => {
+                write!(f, "non-minimal OP_IF argument at offset {}", offset)
+            }
+            ExecError::UnbalancedConditional { offset } => {
+                write!(f, "unbalanced conditional at offset {}", offset)
+            }
+            ExecError::VerifyFailed { offset } => write!(f, "verify failed at offset {}", offset),
+            ExecError::OpReturn { offset } => write!(f, "OP_RETURN at offset {}", offset),
+            ExecError::FinalStack { depth } => write!(
+                f,
+                "final stack is not a single true element ({} elements)",
+                depth
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ExecError {}
+
+/// Run `script` and return the main stack it leaves, bottom first. Numbers
+/// are in script encoding: 0 is the empty element, 1 to 127 a single byte.
+pub fn execute(script: &[u8]) -> Result<Vec<Vec<u8>>, ExecError> {
+    let mut stack: Vec<Vec<u8>> = Vec::new();
+    let mut alt: Vec<Vec<u8>> = Vec::new();
+    // One entry per open OP_IF: whether its current branch executes
+    let mut branches: Vec<bool> = Vec::new();
+
+    let mut pc = 0;
+    while pc < script.len() {
+        let offset = pc;
+        let opcode = script[pc];
+        pc += 1;
+        let executing = branches.iter().all(|&taken| taken);
+
+        // Pushes are parsed even in branches that don't execute
+        if opcode <= 0x4e {
+            let (len, header) = match opcode {
+                0x4c => (read_len(script, pc, 1, offset)?, 1),
+                0x4d => (read_len(script, pc, 2, offset)?, 2),
+                0x4e => (read_len(script, pc, 4, offset)?, 4),
+                n => (n as usize, 0),
+            };
+            pc += header;
+            let data = script
+                .get(pc..pc + len)
+                .ok_or(ExecError::TruncatedPush { offset })?;
+            pc += len;
+            if executing {
+                if !is_minimal_push(opcode, data) {
+                    return Err(ExecError::NonMinimalPush { offset });
+                }
+                stack.push(data.to_vec());
+            }
+        } else {
+            match opcode {
+                // OP_IF, OP_NOTIF
+                0x63 | 0x64 => {
+                    let mut taken = false;
+                    if executing {
+                        let arg = pop(&mut stack, offset)?;
+                        taken = match arg.as_slice() {
+                            [] => false,
+                            [1] => true,
+                            _ => return Err(ExecError::NonMinimalIf { offset }),
+                        };
+                        if opcode == 0x64 {
+                            taken = !taken;
+                        }
+                    }
+                    branches.push(taken);
+                }
+                // OP_ELSE
+                0x67 => {
+                    let taken = branches
+                        .last_mut()
+                        .ok_or(ExecError::UnbalancedConditional { offset })?;
+                    *taken = !*taken;
+                }
+                // OP_ENDIF
+                0x68 => {
+                    branches
+                        .pop()
+                        .ok_or(ExecError::UnbalancedConditional { offset })?;
+                }
+                _ if !executing => {}
+                _ => op(opcode, offset, &mut stack, &mut alt)?,
+            }
+        }
+
+        if stack.len() + alt.len() > MAX_STACK_ELEMENTS {
+            return Err(ExecError::StackOverflow { offset });
+        }
+    }
+
+    if !branches.is_empty() {
+        return Err(ExecError::UnbalancedConditional {
+            offset: script.len(),
+        });
+    }
+    Ok(stack)
+}
+
+/// Run `script` with the success rule of `run_script_checked`: it must leave
+/// exactly one element, and that element must be true.
+pub fn run_checked(script: &[u8]) -> Result<(), ExecError> {
+    let stack = execute(script)?;
+    match stack.as_slice() {
+        [top] if cast_to_bool(top) => Ok(()),
+        _ => Err(ExecError::FinalStack { depth: stack.len() }),
+    }
+}
+
+// Execute one non-push, non-conditional opcode
+fn op(
+    opcode: u8,
+    offset: usize,
+    stack: &mut Vec<Vec<u8>>,
+    alt: &mut Vec<Vec<u8>>,
+) -> Result<(), ExecError> {
+    let underflow = ExecError::StackUnderflow { offset };
+    let depth = stack.len();
+    // Element `n` from the top, 0 being the top
+    let nth = |stack: &Vec<Vec<u8>>, n: usize| {
+        stack
+            .len()
+            .checked_sub(n + 1)
+            .map(|i| stack[i].clone())
+            .ok_or(ExecError::StackUnderflow { offset })
+    };
+
+    match opcode {
+        // OP_1NEGATE, OP_1 to OP_16
+        0x4f => stack.push(encode_num(-1)),
+        0x51..=0x60 => stack.push(encode_num(i64::from(opcode - 0x50))),
+        // OP_NOP
+        0x61 => {}
+        // OP_VERIFY
+        0x69 => {
+            if !cast_to_bool(&pop(stack, offset)?) {
+                return Err(ExecError::VerifyFailed { offset });
+            }
+        }
+        // OP_RETURN
+        0x6a => return Err(ExecError::OpReturn { offset }),
+        // OP_TOALTSTACK, OP_FROMALTSTACK
+        0x6b => alt.push(pop(stack, offset)?),
+        0x6c => stack.push(alt.pop().ok_or(underflow)?),
+        // OP_2DROP
+        0x6d => {
+            if depth < 2 {
+                return Err(underflow);
+            }
+            stack.truncate(depth - 2);
+        }
+        // OP_2DUP, OP_3DUP
+        0x6e | 0x6f => {
+            let n = usize::from(opcode - 0x6c);
+            if depth < n {
+                return Err(underflow);
+            }
+            stack.extend_from_within(depth - n..);
+        }
+        // OP_2OVER
+        0x70 => {
+            if depth < 4 {
+                return Err(underflow);
+            }
+            stack.extend_from_within(depth - 4..depth - 2);
+        }
+        // OP_2ROT
+        0x71 => {
+            if depth < 6 {
+                return Err(underflow);
+            }
+            let moved: Vec<_> = stack.drain(depth - 6..depth - 4).collect();
+            stack.extend(moved);
+        }
+        // OP_2SWAP
+        0x72 => {
+            if depth < 4 {
+                return Err(underflow);
+            }
+            stack[depth - 4..].rotate_left(2);
+        }
+        // OP_IFDUP
+        0x73 => {
+            let top = nth(stack, 0)?;
+            if cast_to_bool(&top) {
+                stack.push(top);
+            }
+        }
+        // OP_DEPTH
+        0x74 => stack.push(encode_num(depth as i64)),
+        // OP_DROP
+        0x75 => {
+            pop(stack, offset)?;
+        }
+        // OP_DUP, OP_OVER
+        0x76 => stack.push(nth(stack, 0)?),
+        0x78 => stack.push(nth(stack, 1)?),
+        // OP_NIP
+        0x77 => {
+            if depth < 2 {
+                return Err(underflow);
+            }
+            stack.remove(depth - 2);
+        }
+        // OP_PICK, OP_ROLL
+        0x79 | 0x7a => {
+            let n = pop_num(stack, offset)?;
+            let n = usize::try_from(n).map_err(|_| ExecError::InvalidNumber { offset })?;
+            if n >= stack.len() {
+                return Err(underflow);
+            }
+            let index = stack.len() - 1 - n;
+            let element = if opcode == 0x79 {
+                stack[index].clone()
+            } else {
+                stack.remove(index)
+            };
+            stack.push(element);
+        }
+        // OP_ROT
+        0x7b => {
+            if depth < 3 {
+                return Err(underflow);
+            }
+            stack[depth - 3..].rotate_left(1);
+        }
+        // OP_SWAP
+        0x7c => {
+            if depth < 2 {
+                return Err(underflow);
+            }
+            stack.swap(depth - 2, depth - 1);
+        }
+        // OP_TUCK
+        0x7d => {
+            let top = nth(stack, 0)?;
+            if depth < 2 {
+                return Err(underflow);
+            }
+            stack.insert(depth - 2, top);
+        }
+        // OP_SIZE
+        0x82 => {
+            let size = nth(stack, 0)?.len();
+            stack.push(encode_num(size as i64));
+        }
+        // OP_EQUAL, OP_EQUALVERIFY
+        0x87 | 0x88 => {
+            let b = pop(stack, offset)?;
+            let a = pop(stack, offset)?;
+            if opcode == 0x88 {
+                if a != b {
+                    return Err(ExecError::VerifyFailed { offset });
+                }
+            } else {
+                stack.push(encode_bool(a == b));
+            }
+        }
+        // OP_1ADD to OP_0NOTEQUAL, skipping the disabled OP_2MUL and OP_2DIV
+        0x8b | 0x8c | 0x8f..=0x92 => {
+            let a = pop_num(stack, offset)?;
+            let result = match opcode {
+                0x8b => a + 1,
+                0x8c => a - 1,
+                0x8f => -a,
+                0x90 => a.abs(),
+                0x91 => i64::from(a == 0),
+                _ => i64::from(a != 0),
+            };
+            stack.push(encode_num(result));
+        }
+        // OP_ADD to OP_MAX, skipping the disabled multiplication and shifts
+        0x93 | 0x94 | 0x9a..=0xa4 => {
+            let b = pop_num(stack, offset)?;
+            let a = pop_num(stack, offset)?;
+            let result = match opcode {
+                0x93 => a + b,
+                0x94 => a - b,
+                0x9a => i64::from(a != 0 && b != 0),
+                0x9b => i64::from(a != 0 || b != 0),
+                0x9c | 0x9d => i64::from(a == b),
+                0x9e => i64::from(a != b),
+                0x9f => i64::from(a < b),
+                0xa0 => i64::from(a > b),
+                0xa1 => i64::from(a <= b),
+                0xa2 => i64::from(a >= b),
+                0xa3 => a.min(b),
+                _ => a.max(b),
+            };
+            if opcode == 0x9d {
+                if result == 0 {
+                    return Err(ExecError::VerifyFailed { offset });
+                }
+            } else {
+                stack.push(encode_num(result));
+            }
+        }
+        // OP_WITHIN
+        0xa5 => {
+            let max = pop_num(stack, offset)?;
+            let min = pop_num(stack, offset)?;
+            let x = pop_num(stack, offset)?;
+            stack.push(encode_bool(min <= x && x < max));
+        }
+        _ => return Err(ExecError::UnsupportedOpcode { offset, opcode }),
+    }
+    Ok(())
+}
+
+fn pop(stack: &mut Vec<Vec<u8>>, offset: usize) -> Result<Vec<u8>, ExecError> {
+    stack.pop().ok_or(ExecError::StackUnderflow { offset })
+}
+
+fn pop_num(stack: &mut Vec<Vec<u8>>, offset: usize) -> Result<i64, ExecError> {
+    decode_num(&pop(stack, offset)?).ok_or(ExecError::InvalidNumber { offset })
+}
+
+// Little-endian length of a PUSHDATA opcode, `width` bytes at `pc`
+fn read_len(script: &[u8], pc: usize, width: usize, offset: usize) -> Result<usize, ExecError> {
+    let bytes = script
+        .get(pc..pc + width)
+        .ok_or(ExecError::TruncatedPush { offset })?;
+    Ok(bytes
+        .iter()
+        .rev()
+        .fold(0, |len, &byte| (len << 8) | usize::from(byte)))
+}
+
+// The shortest push of `data`, as the MINIMALDATA rule requires
+fn is_minimal_push(opcode: u8, data: &[u8]) -> bool {
+    match data {
+        [] => opcode == 0x00,
+        // OP_1 to OP_16 and OP_1NEGATE
+        [1..=16] | [0x81] => false,
+        _ if data.len() <= 0x4b => usize::from(opcode) == data.len(),
+        _ if data.len() <= 0xff => opcode == 0x4c,
+        _ if data.len() <= 0xffff => opcode == 0x4d,
+        _ => opcode == 0x4e,
+    }
+}
+
+// Minimally encoded little-endian sign-magnitude number of at most 4 bytes
+fn decode_num(bytes: &[u8]) -> Option<i64> {
+    let (&last, rest) = match bytes.split_last() {
+        None => return Some(0),
+        Some(split) => split,
+    };
+    if bytes.len() > MAX_NUM_BYTES {
+        return None;
+    }
+    // A zero top byte is only allowed to make room for the sign bit
+    if last & 0x7f == 0 && rest.last().is_none_or(|&byte| byte & 0x80 == 0) {
+        return None;
+    }
+    let magnitude = bytes
+        .iter()
+        .rev()
+        .fold(0i64, |acc, &byte| (acc << 8) | i64::from(byte))
+        & !(0x80 << (8 * rest.len()));
+    Some(if last & 0x80 != 0 {
+        -magnitude
+    } else {
+        magnitude
+    })
+}
+
+fn encode_num(n: i64) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut magnitude = n.unsigned_abs();
+    while magnitude > 0 {
+        bytes.push(magnitude as u8);
+        magnitude >>= 8;
+    }
+    if let Some(&last) = bytes.last() {
+        if last & 0x80 != 0 {
+            bytes.push(if n < 0 { 0x80 } else { 0 });
+        } else if n < 0 {
+            *bytes.last_mut().unwrap() |= 0x80;
+        }
+    }
+    bytes
+}
+
+fn encode_bool(b: bool) -> Vec<u8> {
+    encode_num(i64::from(b))
+}
+
+// False is any encoding of zero, negative zero included
+fn cast_to_bool(bytes: &[u8]) -> bool {
+    match bytes.split_last() {
+        None => false,
+        Some((&last, rest)) => rest.iter().any(|&byte| byte != 0) || last & 0x7f != 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_numbers() {
+        for n in [
+            0i64,
+            1,
+            -1,
+            16,
+            127,
+            128,
+            -128,
+            255,
+            256,
+            0x7fff_ffff,
+            -0x7fff_ffff,
+        ] {
+            assert_eq!(decode_num(&encode_num(n)), Some(n), "{}", n);
+        }
+        assert_eq!(encode_num(0), Vec::<u8>::new());
+        assert_eq!(encode_num(128), [0x80, 0x00]);
+        assert_eq!(encode_num(-128), [0x80, 0x80]);
+        // Non-minimal and oversized operands are rejected
+        assert_eq!(decode_num(&[0x01, 0x00]), None);
+        assert_eq!(decode_num(&[0x80]), None);
+        assert_eq!(decode_num(&[0, 0, 0, 0x80, 0]), None);
+        assert!(!cast_to_bool(&[0x00, 0x80]));
+        assert!(cast_to_bool(&[0x00, 0x01]));
+    }
+
+    #[test]
+    fn test_execute() {
+        // 3 5 OP_ADD 8 OP_EQUALVERIFY 1 2 OP_SWAP OP_SUB
+        let script = [0x53, 0x55, 0x93, 0x58, 0x88, 0x51, 0x52, 0x7c, 0x94];
+        assert_eq!(execute(&script), Ok(vec![vec![0x01]]));
+
+        // 0 OP_IF 7 OP_ELSE 9 OP_ENDIF, then 9 OP_EQUAL
+        let branch = [0x00, 0x63, 0x57, 0x67, 0x59, 0x68, 0x59, 0x87];
+        assert_eq!(run_checked(&branch), Ok(()));
+
+        // 1 2 3 2 OP_PICK, then 2 OP_ROLL: [1 2 3 1] -> [1 3 1 2]
+        let pick_roll = [0x51, 0x52, 0x53, 0x52, 0x79, 0x52, 0x7a];
+        assert_eq!(
+            execute(&pick_roll),
+            Ok(vec![vec![1], vec![3], vec![1], vec![2]])
+        );
+
+        // 4 OP_TOALTSTACK 5 OP_FROMALTSTACK: alt stack is last in, first out
+        assert_eq!(
+            execute(&[0x54, 0x6b, 0x55, 0x6c]),
+            Ok(vec![vec![5], vec![4]])
+        );
+
+        // 15 in [0, 16), 16 not
+        assert_eq!(run_checked(&[0x5f, 0x00, 0x60, 0xa5]), Ok(()));
+        assert!(run_checked(&[0x60, 0x00, 0x60, 0xa5]).is_err());
+    }
+
+    #[test]
+    fn test_execute_errors() {
+        assert_eq!(
+            execute(&[0x75]),
+            Err(ExecError::StackUnderflow { offset: 0 })
+        );
+        assert_eq!(
+            execute(&[0x51, 0x52, 0x87, 0x69]),
+            Err(ExecError::VerifyFailed { offset: 3 })
+        );
+        // One-byte push of 5 instead of OP_5
+        assert_eq!(
+            execute(&[0x01, 0x05]),
+            Err(ExecError::NonMinimalPush { offset: 0 })
+        );
+        assert_eq!(
+            execute(&[0x02, 0x05]),
+            Err(ExecError::TruncatedPush { offset: 0 })
+        );
+        // OP_IF on 2
+        assert_eq!(
+            execute(&[0x52, 0x63, 0x68]),
+            Err(ExecError::NonMinimalIf { offset: 1 })
+        );
+        assert_eq!(
+            execute(&[0x51, 0x63]),
+            Err(ExecError::UnbalancedConditional { offset: 2 })
+        );
+        // OP_CHECKSIG is out of scope, but only when executed
+        assert_eq!(
+            execute(&[0xac]),
+            Err(ExecError::UnsupportedOpcode {
+                offset: 0,
+                opcode: 0xac
+            })
+        );
+        assert_eq!(execute(&[0x00, 0x63, 0xac, 0x68]), Ok(vec![]));
+        assert_eq!(
+            run_checked(&[0x51, 0x51]),
+            Err(ExecError::FinalStack { depth: 2 })
+        );
+
+        let overflow = vec![0x51; MAX_STACK_ELEMENTS + 1];
+        assert_eq!(
+            execute(&overflow),
+            Err(ExecError::StackOverflow {
+                offset: MAX_STACK_ELEMENTS
+            })
+        );
+    }
+}
@@ -1,16 +1,67 @@
 //! Fixed Optimized STACKSAT-128 Bitcoin Script Implementation
 //! This version fixes compilation errors and works within your bitcoin_script constraints
+use std::collections::BTreeMap;
+
 use bitcoin::hex::FromHex;
+use bitcoin::opcodes::Opcode;
 use bitcoin_script_stack::stack::StackTracker;
 
 pub use bitcoin_script::builder::StructuredScript as Script;
 pub use bitcoin_script::script;
 use bitvm::bigint::U256;
+use bitvm::execute_script_buf;
+use stacksat128::padding;
+
+pub mod interpreter;
+
+/// The canonical STACKSAT-128 hash, re-exported from the `stacksat128` crate.
+/// That crate is the reference implementation; this crate only generates
+/// scripts that reproduce it and has no hash implementation of its own.
+pub use stacksat128::stacksat_hash as reference_hash;
+
+/// Longest message, in bytes, the script generators accept. Scripts for
+/// messages longer than `MAX_EXECUTABLE_MESSAGE_BYTES` generate but cannot
+/// run: they break the 1,000-element stack limit.
+pub const MAX_MESSAGE_BYTES: usize = 1024;
+
+/// Longest message, in bytes, whose compute script stays within the
+/// 1,000-element limit on the main and alt stacks together. The compute script
+/// parks every padded message nibble on the alt stack. Absorbing the first
+/// block then needs the 64-nibble state plus one working element on the main
+/// stack, which is the peak. That allows 935 parked nibbles, rounded down to
+/// whole 16-byte blocks: 928 nibbles, or 464 bytes. The limit assumes the
+/// message nibbles are already on the stack, as a witness supplies them.
+pub const MAX_EXECUTABLE_MESSAGE_BYTES: usize = 464;
+
+// Limb sizes in bits: messages and digests are pushed as bytes and converted
+// to the nibbles the compute script works on.
+const BYTE_LIMB_BITS: u32 = 8;
+const NIBBLE_LIMB_BITS: u32 = 4;
+
+/// Limb sizes, in bits, the compute script takes its message in. Only 4-bit
+/// nibbles; `stacksat128_push_message_script` converts bytes to nibbles.
+pub fn supported_limb_sizes() -> &'static [u8] {
+    &[NIBBLE_LIMB_BITS as u8]
+}
+
+// Panics unless the generators support `message_len`-byte messages.
+fn assert_message_len(message_len: usize) {
+    assert!(
+        message_len <= MAX_MESSAGE_BYTES,
+        "STACKSAT-128: Message length > {} bytes not supported",
+        MAX_MESSAGE_BYTES
+    );
+}
 
 // --- Constants (keeping your existing ones) ---
-const STACKSATSCRIPT_RATE_NIBBLES: usize = 32;
-const STACKSATSCRIPT_STATE_NIBBLES: usize = 64;
-const STACKSATSCRIPT_ROUNDS: usize = 16;
+/// Message nibbles absorbed per block: the compute script takes the message
+/// as consecutive groups of this many nibbles.
+pub const STACKSATSCRIPT_RATE_NIBBLES: usize = 32;
+/// Nibbles of sponge state, and of the digest the compute script leaves on
+/// the stack.
+pub const STACKSATSCRIPT_STATE_NIBBLES: usize = 64;
+/// Rounds of the permutation per block.
+pub const STACKSATSCRIPT_ROUNDS: usize = 16;
 const STACKSATSCRIPT_EMPTY_MSG_HASH: &str =
     "c5f691c6a65b0f446c17528b805359bce646bf0905e1418b4f25fe442be9f714";
 const STACKSATSCRIPT_SBOX: [u8; 16] = [
@@ -19,6 +70,22 @@ const STACKSATSCRIPT_SBOX: [u8; 16] = [
 const STACKSATSCRIPT_RC: [u8; STACKSATSCRIPT_ROUNDS] =
     [1, 8, 12, 14, 15, 7, 11, 5, 10, 13, 6, 3, 9, 4, 2, 1];
 
+// Fail the build if the round-constant table loses its length or gains a zero.
+const _: () = {
+    assert!(
+        STACKSATSCRIPT_RC.len() == 16,
+        "RC must have 16 round constants"
+    );
+    let mut i = 0;
+    while i < STACKSATSCRIPT_RC.len() {
+        assert!(
+            STACKSATSCRIPT_RC[i] != 0 && STACKSATSCRIPT_RC[i] <= 0xF,
+            "RC must be non-zero nibbles"
+        );
+        i += 1;
+    }
+};
+
 fn generate_optimized_sbox_script() -> Script {
     // Create a script that efficiently substitutes all 64 nibbles using a lookup table approach
     // that's compatible with your Bitcoin Script library constraints
@@ -43,6 +110,89 @@ fn generate_optimized_sbox_script() -> Script {
     }
 }
 
+// S-box for the nibble on top of the stack as a chain of comparisons, so no
+// table sits on the stack: the last branch needs no test since inputs are
+// nibbles.
+fn generate_conditional_sbox_nibble() -> Script {
+    script! {
+        for value in 0..15 {
+            OP_DUP { value as u32 } OP_EQUAL
+            OP_IF
+                OP_DROP { STACKSATSCRIPT_SBOX[value] }
+            OP_ELSE
+        }
+        OP_DROP { STACKSATSCRIPT_SBOX[15] }
+        for _ in 0..15 {
+            OP_ENDIF
+        }
+    }
+}
+
+fn generate_conditional_sbox_script() -> Script {
+    let nibble = generate_conditional_sbox_nibble();
+    script! {
+        // Same order as the table version: substitute from the top down through
+        // the altstack, then bring the nibbles back
+        for _ in 0..STACKSATSCRIPT_STATE_NIBBLES {
+            { nibble.clone() }
+            OP_TOALTSTACK
+        }
+        for _ in 0..STACKSATSCRIPT_STATE_NIBBLES {
+            OP_FROMALTSTACK
+        }
+    }
+}
+
+/// How the compute script realizes the S-box. Both give the same digests; they
+/// trade script size against stack depth (see `stacksat128_sbox_stats`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SboxStrategy {
+    /// Push the 16-entry table above the state once per round and look each
+    /// nibble up with `OP_PICK`. The smallest script.
+    #[default]
+    Table,
+    /// Map each nibble through a chain of `OP_EQUAL`/`OP_IF` branches. Needs
+    /// only two stack slots above the state, at many times the size.
+    Conditional,
+}
+
+impl SboxStrategy {
+    fn layer_script(self) -> Script {
+        match self {
+            SboxStrategy::Table => generate_optimized_sbox_script(),
+            SboxStrategy::Conditional => generate_conditional_sbox_script(),
+        }
+    }
+
+    // Most elements the layer keeps on the main stack above the 64 state
+    // nibbles: the table, or the duplicated nibble and its comparand.
+    fn extra_stack_items(self) -> usize {
+        match self {
+            SboxStrategy::Table => 16,
+            SboxStrategy::Conditional => 2,
+        }
+    }
+}
+
+/// Cost of one round's S-box layer under an `SboxStrategy`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SboxStats {
+    /// Compiled size of the layer, in bytes. The compute script runs it 16
+    /// times per block.
+    pub script_size: usize,
+    /// Most main-stack elements the layer needs on top of the 64 state nibbles.
+    pub extra_stack_items: usize,
+}
+
+/// Size and stack depth of the S-box layer built with `strategy`, for choosing
+/// between byte size and stack-depth limits.
+pub fn stacksat128_sbox_stats(strategy: SboxStrategy) -> SboxStats {
+    SboxStats {
+        script_size: strategy.layer_script().compile().to_bytes().len(),
+        extra_stack_items: strategy.extra_stack_items(),
+    }
+}
+
 fn generate_efficient_mod16_add() -> Script {
     script! {
         // Input: two nibbles on stack
@@ -155,73 +305,97 @@ fn generate_optimized_permutation() -> Script {
     }
 }
 
-fn generate_optimized_mixcolumns() -> Script {
-    let mut mix_script = script!();
+// MixColumns for one output position: p0 + p1 + p2 + p3 (mod 16), rolling the
+// inputs that are not read again and picking the rest.
+fn generate_mixcolumn_position(position: usize) -> Script {
+    let depths = &STACKATSCRIPT_MIXCOLUMN_DEPTHS[position];
+    script! {
+        // Pick p0 to the top of the stack
+        { depths.depths[0] }
+        // If the position will be removed, roll the stack, otherwise pick the value
+        if depths.will_remove[0] {
+            OP_ROLL
+        } else {
+            OP_PICK
+        }
+        // Pick p1 to the top of the stack
+        { depths.depths[1] + 1 }
+        // If the position will be removed, roll the stack, otherwise pick the value
+        if depths.will_remove[1] {
+            OP_ROLL
+        } else {
+            OP_PICK
+        }
+        // p0 + p1
+        OP_ADD
 
-    // For each position in the state
-    for r_idx in 0..8 {
-        for c_idx in 0..8 {
-            let position = r_idx * 8 + c_idx;
+        // Pick p2 to the top of the stack
+        { depths.depths[2] + 1 }
+        // If the position will be removed, roll the stack, otherwise pick the value
+        if depths.will_remove[2] {
+            OP_ROLL
+        } else {
+            OP_PICK
+        }
+        // Pick p3 to the top of the stack
+        { depths.depths[3] + 2 }
+        // If the position will be removed, roll the stack, otherwise pick the value
+        if depths.will_remove[3] {
+            OP_ROLL
+        } else {
+            OP_PICK
+        }
+        // Then add p2 + p3
+        OP_ADD
 
-            // Build script for this position
-            mix_script = script!(
-                { mix_script }
-                // Pick p0 to the top of the stack
-                { STACKATSCRIPT_MIXCOLUMN_DEPTHS[position].depths[0] }
-                // If the position will be removed, roll the stack, otherwise pick the value
-                if STACKATSCRIPT_MIXCOLUMN_DEPTHS[position].will_remove[0] {
-                    OP_ROLL
-                } else {
-                    OP_PICK
-                }
-                // Pick p1 to the top of the stack
-                { STACKATSCRIPT_MIXCOLUMN_DEPTHS[position].depths[1] + 1 }
-                // If the position will be removed, roll the stack, otherwise pick the value
-                if STACKATSCRIPT_MIXCOLUMN_DEPTHS[position].will_remove[1] {
-                    OP_ROLL
-                } else {
-                    OP_PICK
-                }
-                // p0 + p1
-                OP_ADD
-
-                // Pick p2 to the top of the stack
-                { STACKATSCRIPT_MIXCOLUMN_DEPTHS[position].depths[2] + 1 }
-                // If the position will be removed, roll the stack, otherwise pick the value
-                if STACKATSCRIPT_MIXCOLUMN_DEPTHS[position].will_remove[2] {
-                    OP_ROLL
-                } else {
-                    OP_PICK
-                }
-                // Pick p3 to the top of the stack
-                { STACKATSCRIPT_MIXCOLUMN_DEPTHS[position].depths[3] + 2 }
-                // If the position will be removed, roll the stack, otherwise pick the value
-                if STACKATSCRIPT_MIXCOLUMN_DEPTHS[position].will_remove[3] {
-                    OP_ROLL
-                } else {
-                    OP_PICK
-                }
-                // Then add p2 + p3
-                OP_ADD
+        // Finally add (p0+p1)+(p2+p3)
+        OP_ADD
+        // Mod 16
+        { generate_mod64_to_mod16() }
+    }
+}
 
-                // Finally add (p0+p1)+(p2+p3)
-                OP_ADD
-                // Mod 16
-                { generate_mod64_to_mod16() }
-            );
+fn generate_optimized_mixcolumns() -> Script {
+    generate_mixcolumns_positions(STACKSATSCRIPT_STATE_NIBBLES)
+}
+
+// MixColumns for the first `positions` output positions. The positions are built
+// as separate fragments and joined in one `script!`, rather than re-wrapping the
+// accumulated script once per position, so generation is linear in `positions`.
+fn generate_mixcolumns_positions(positions: usize) -> Script {
+    script! {
+        for position in 0..positions {
+            { generate_mixcolumn_position(position) }
         }
     }
-    mix_script
+}
+
+/// MixColumns section on its own, as used in every round. Exposed for the
+/// script generation benchmark.
+#[doc(hidden)]
+pub fn stacksat128_mixcolumns_script() -> Script {
+    generate_optimized_mixcolumns()
+}
+
+/// MixColumns for the first `positions` output positions. Exposed for the
+/// script generation benchmark, which compares 16 and 64 positions.
+#[doc(hidden)]
+pub fn stacksat128_mixcolumns_positions_script(positions: usize) -> Script {
+    generate_mixcolumns_positions(positions)
 }
 
 fn generate_optimized_round(round_idx: usize) -> Script {
+    generate_round(round_idx, SboxStrategy::Table)
+}
+
+fn generate_round(round_idx: usize, sbox: SboxStrategy) -> Script {
     let round_constant = STACKSATSCRIPT_RC[round_idx];
 
     script! {
         // Combine all round operations efficiently
 
-        // Step 1: S-box substitution (optimized)
-        { generate_optimized_sbox_script() }
+        // Step 1: S-box substitution
+        { sbox.layer_script() }
 
         // Step 2: Permutation (optimized)
         { generate_optimized_permutation() }
@@ -375,41 +549,41 @@ const STACKATSCRIPT_MIXCOLUMN_DEPTHS: [PositionInfo; STACKSATSCRIPT_STATE_NIBBLE
     depths
 };
 
-// Main optimized implementation
-fn stacksat128_optimized(stack: &mut StackTracker, msg_len: usize, define_var: bool) {
+// Whether the empty message is served by pushing the precomputed digest. The
+// constant is only valid for the full round count.
+fn uses_empty_fast_path(msg_len: usize, rounds: usize, empty_fast_path: bool) -> bool {
+    msg_len == 0 && empty_fast_path && rounds == STACKSATSCRIPT_ROUNDS
+}
+
+// Labeled script sections making up the compute script, in execution order.
+// The labels are the names handed to `StackTracker::custom`.
+fn stacksat128_sections(
+    msg_len: usize,
+    rounds: usize,
+    empty_fast_path: bool,
+    sbox: SboxStrategy,
+) -> Vec<(String, Script)> {
+    let mut sections = Vec::new();
+
     // Handle empty message case (keep existing - it's already optimal)
-    if msg_len == 0 {
+    if uses_empty_fast_path(msg_len, rounds, empty_fast_path) {
         let empty_msg_hash_bytearray = <[u8; 32]>::from_hex(STACKSATSCRIPT_EMPTY_MSG_HASH).unwrap();
 
-        stack.custom(
+        sections.push((
+            "optimized_empty_hash".to_string(),
             script!(
                 for byte in empty_msg_hash_bytearray {
                     {byte}
                 }
-                {U256::transform_limbsize(8, 4)}
+                {U256::transform_limbsize(BYTE_LIMB_BITS, NIBBLE_LIMB_BITS)}
             ),
-            0,
-            false,
-            0,
-            "optimized_empty_hash",
-        );
-        stack.define(64, "stacksat128_optimized_hash");
-        return;
+        ));
+        return sections;
     }
 
-    // Message preparation (optimized but keeping your working approach)
-    let mut msg_nibbles_len = msg_len * 2;
-    if define_var {
-        for i in 0..msg_nibbles_len as usize {
-            stack.define(1, &format!("opt_msg_{}", i));
-        }
-    }
-
-    let padding_len = (STACKSATSCRIPT_RATE_NIBBLES - msg_nibbles_len % STACKSATSCRIPT_RATE_NIBBLES)
-        % STACKSATSCRIPT_RATE_NIBBLES;
-    msg_nibbles_len += padding_len;
-    let padding_script = generate_push_script(0, padding_len);
-    stack.custom(padding_script, 0, false, 0, "optimized_padding");
+    let msg_nibbles_len = padding::padded_nibble_len(msg_len);
+    let padding_script = generate_push_script(0, padding::padding_nibbles(msg_len));
+    sections.push(("optimized_padding".to_string(), padding_script));
 
     // Move the message to the altstack
     let move_msg_to_altstack_script = script! {
@@ -417,74 +591,271 @@ fn stacksat128_optimized(stack: &mut StackTracker, msg_len: usize, define_var: b
             OP_TOALTSTACK
         }
     };
-    stack.custom(
+    sections.push((
+        "optimized_move_msg_to_altstack".to_string(),
         move_msg_to_altstack_script,
-        0,
-        false,
-        0,
-        "optimized_move_msg_to_altstack",
-    );
+    ));
 
     // Initialize state efficiently
     let state_init_script = generate_push_script(0, STACKSATSCRIPT_STATE_NIBBLES);
-    stack.custom(state_init_script, 0, false, 0, "optimized_state_init");
+    sections.push(("optimized_state_init".to_string(), state_init_script));
 
     // Main processing loop (optimized)
-    let num_blocks = msg_nibbles_len / STACKSATSCRIPT_RATE_NIBBLES;
+    let num_blocks = padding::block_count(msg_len);
+
+    // Every block runs the same absorption and rounds, so generate them once
+    // and clone them per block rather than rebuilding them for each block.
+    let absorption_script = generate_optimized_absorption();
+    let round_scripts: Vec<Script> = (0..rounds)
+        .map(|round_idx| generate_round(round_idx, sbox))
+        .collect();
 
     for block_idx in 0..num_blocks {
         // Optimized absorption
-        let absorption_script = generate_optimized_absorption();
-        stack.custom(
-            absorption_script,
-            0,
-            false,
-            0,
-            &format!("opt_absorb_{}", block_idx),
-        );
+        sections.push((
+            format!("opt_absorb_{}", block_idx),
+            absorption_script.clone(),
+        ));
 
         // Optimized permutation rounds
-        for round_idx in 0..STACKSATSCRIPT_ROUNDS {
-            let round_script = generate_optimized_round(round_idx);
-            stack.custom(
-                round_script,
-                0,
-                false,
-                0,
-                &format!("opt_round_{}_{}", block_idx, round_idx),
-            );
+        for (round_idx, round_script) in round_scripts.iter().enumerate() {
+            sections.push((
+                format!("opt_round_{}_{}", block_idx, round_idx),
+                round_script.clone(),
+            ));
+        }
+    }
+    sections
+}
+
+// Main optimized implementation
+fn stacksat128_optimized(
+    stack: &mut StackTracker,
+    msg_len: usize,
+    rounds: usize,
+    empty_fast_path: bool,
+    define_var: bool,
+    sbox: SboxStrategy,
+) {
+    #[cfg(feature = "debug_stack")]
+    stack.debug();
+
+    // Message preparation (optimized but keeping your working approach)
+    if msg_len > 0 && define_var {
+        for i in 0..msg_len * 2 {
+            stack.define(1, &format!("opt_msg_{}", i));
         }
     }
+
+    for (label, section) in stacksat128_sections(msg_len, rounds, empty_fast_path, sbox) {
+        stack.custom(section, 0, false, 0, &label);
+    }
+
+    if uses_empty_fast_path(msg_len, rounds, empty_fast_path) {
+        stack.define(64, "stacksat128_optimized_hash");
+    }
 }
 
 // Public interface functions
 pub fn stacksat128_compute_script_with_limb(message_len: usize) -> Script {
-    assert!(
-        message_len <= 1024,
-        "STACKSAT-128: Message length > 1024 bytes not supported"
-    );
+    assert_message_len(message_len);
     let mut stack = StackTracker::new();
-    stacksat128_optimized(&mut stack, message_len, true);
+    stacksat128_optimized(
+        &mut stack,
+        message_len,
+        STACKSATSCRIPT_ROUNDS,
+        true,
+        true,
+        SboxStrategy::Table,
+    );
     stack.get_script()
 }
 
 // Add optimized version selector
 pub fn stacksat128_compute_script_optimized(message_len: usize) -> Script {
+    assert_message_len(message_len);
+    let mut stack = StackTracker::new();
+    stacksat128_optimized(
+        &mut stack,
+        message_len,
+        STACKSATSCRIPT_ROUNDS,
+        true,
+        true,
+        SboxStrategy::Table,
+    );
+    stack.get_script()
+}
+
+/// Compute script with the S-box realized by `sbox`. `SboxStrategy::Table` is
+/// `stacksat128_compute_script_optimized`; every strategy computes the same
+/// digest.
+pub fn stacksat128_compute_script_with_sbox(message_len: usize, sbox: SboxStrategy) -> Script {
+    assert_message_len(message_len);
+    let mut stack = StackTracker::new();
+    stacksat128_optimized(
+        &mut stack,
+        message_len,
+        STACKSATSCRIPT_ROUNDS,
+        true,
+        true,
+        sbox,
+    );
+    stack.get_script()
+}
+
+/// Compute script for the empty message that runs the sponge (absorb one zero
+/// block, 16 rounds) instead of pushing the precomputed digest. Useful for
+/// checking the sponge machinery against the hardcoded constant.
+pub fn stacksat128_compute_script_empty_honest() -> Script {
+    let mut stack = StackTracker::new();
+    stacksat128_optimized(
+        &mut stack,
+        0,
+        STACKSATSCRIPT_ROUNDS,
+        false,
+        true,
+        SboxStrategy::Table,
+    );
+    stack.get_script()
+}
+
+/// Compute script running only the first `rounds` rounds (1 to 16) of the
+/// permutation per block, for studying script size against round count and
+/// building reduced-round challenges. `rounds = 16` is the standard script matching
+/// `stacksat_hash`; anything less is NOT STACKSAT-128 and offers no security.
+/// Reduced-round scripts always run the sponge, even for the empty message.
+pub fn stacksat128_compute_script_rounds(message_len: usize, rounds: usize) -> Script {
+    assert_message_len(message_len);
     assert!(
-        message_len <= 1024,
-        "STACKSAT-128: Message length > 1024 bytes not supported"
+        (1..=STACKSATSCRIPT_ROUNDS).contains(&rounds),
+        "STACKSAT-128: rounds must be 1 to {}, got {}",
+        STACKSATSCRIPT_ROUNDS,
+        rounds
     );
     let mut stack = StackTracker::new();
-    stacksat128_optimized(&mut stack, message_len, true);
+    stacksat128_optimized(
+        &mut stack,
+        message_len,
+        rounds,
+        true,
+        true,
+        SboxStrategy::Table,
+    );
     stack.get_script()
 }
 
-// Keep your existing helper functions
-pub fn stacksat128_push_message_script(message_bytes: &[u8]) -> Script {
+/// Renders the compute script as opcode mnemonics for auditing. Each section is
+/// preceded by a `# <label>` comment line carrying the name it is built under
+/// (e.g. `opt_absorb_0`, `opt_round_0_15`).
+pub fn stacksat128_script_asm(message_len: usize) -> String {
+    assert_message_len(message_len);
+    let mut asm = String::new();
+    for (label, section) in stacksat128_sections(
+        message_len,
+        STACKSATSCRIPT_ROUNDS,
+        true,
+        SboxStrategy::Table,
+    ) {
+        asm.push_str(&format!("# {}\n", label));
+        asm.push_str(&section.compile().to_asm_string());
+        asm.push('\n');
+    }
+    asm
+}
+
+/// The compute script for a `message_len`-byte message as labeled sections in
+/// execution order, under the names `stacksat128_script_asm` prints.
+/// Concatenating the sections reproduces `stacksat128_compute_script_optimized`,
+/// so tooling can inspect or rewrite single sections (e.g. insert assertions
+/// between rounds) and reassemble them.
+pub fn stacksat128_script_fragments(message_len: usize) -> Vec<(String, Script)> {
+    assert_message_len(message_len);
+    stacksat128_sections(
+        message_len,
+        STACKSATSCRIPT_ROUNDS,
+        true,
+        SboxStrategy::Table,
+    )
+}
+
+/// Script of permutation round `round_idx` on its own, as it appears in every
+/// block of the compute script (the `opt_round_<block>_<round_idx>` sections).
+pub fn stacksat128_round_script(round_idx: usize) -> Script {
     assert!(
-        message_bytes.len() <= 1024,
-        "This STACKSAT-128 implementation doesn't support messages longer than 1024 bytes"
+        round_idx < STACKSATSCRIPT_ROUNDS,
+        "STACKSAT-128: round index {} out of range, there are {} rounds",
+        round_idx,
+        STACKSATSCRIPT_ROUNDS
     );
+    generate_optimized_round(round_idx)
+}
+
+/// Tallies how many times each opcode appears in the compute script, keyed by
+/// mnemonic (e.g. `"OP_PICK"`). Data pushes count under their push opcode
+/// (`OP_PUSHBYTES_n`). Mnemonics are used as keys because `Opcode` has no
+/// ordering.
+pub fn stacksat128_opcode_histogram(message_len: usize) -> BTreeMap<String, usize> {
+    opcode_histogram(stacksat128_compute_script_optimized(message_len))
+}
+
+fn opcode_histogram(script: Script) -> BTreeMap<String, usize> {
+    let compiled = bitcoin::ScriptBuf::from_bytes(script.compile().to_bytes());
+    let bytes = compiled.as_bytes();
+    let mut histogram = BTreeMap::new();
+    for instruction in compiled.instruction_indices() {
+        let (idx, _) = instruction.expect("generated script is well-formed");
+        let opcode = Opcode::from(bytes[idx]);
+        *histogram.entry(opcode.to_string()).or_insert(0) += 1;
+    }
+    histogram
+}
+
+// Relative cost of one execution of the opcode named `mnemonic`.
+fn opcode_weight(mnemonic: &str) -> u64 {
+    match mnemonic {
+        "OP_ROLL" => 4,
+        "OP_PICK" => 3,
+        "OP_TOALTSTACK" | "OP_FROMALTSTACK" => 2,
+        _ => 1,
+    }
+}
+
+fn weighted_cost(histogram: &BTreeMap<String, usize>) -> u64 {
+    histogram
+        .iter()
+        .map(|(mnemonic, count)| opcode_weight(mnemonic) * *count as u64)
+        .sum()
+}
+
+/// Rough execution cost of the compute script for a `message_len`-byte message,
+/// for budgeting BitVM chunks.
+///
+/// The cost is the weighted sum of `stacksat128_opcode_histogram`. Opcodes that
+/// copy or move an element from depth are the expensive ones: `OP_ROLL` (moves
+/// it and shifts everything above) weighs 4, `OP_PICK` 3, altstack moves 2, and
+/// every other opcode, data pushes included, 1. The count is static, so the
+/// bodies of the mod-16 reductions' `OP_IF`s are counted as always taken and
+/// the estimate errs high.
+pub fn stacksat128_execution_cost(message_len: usize) -> u64 {
+    weighted_cost(&stacksat128_opcode_histogram(message_len))
+}
+
+/// Order of the two nibbles of each message byte on the stack.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NibbleOrder {
+    /// High nibble first, then low: the canonical layout the compute script
+    /// expects, as produced by `U256::transform_limbsize(8, 4)`.
+    MsbFirst,
+    /// Low nibble first, then high, as emitted by LSB-first limb encoders.
+    LsbFirst,
+}
+
+// Keep your existing helper functions
+/// Pushes `message_bytes` in the canonical layout: for each byte in order, its
+/// high nibble then its low nibble, so the last byte's low nibble ends on top.
+/// Same as `stacksat128_push_message_script_with_order` with `MsbFirst`.
+pub fn stacksat128_push_message_script(message_bytes: &[u8]) -> Script {
+    assert_message_len(message_bytes.len());
     let chunks = chunk_message(message_bytes);
     let needed_padding_bytes = if message_bytes.len() % 32 == 0 {
         0
@@ -498,7 +869,7 @@ pub fn stacksat128_push_message_script(message_bytes: &[u8]) -> Script {
             for (i, byte) in chunk.into_iter().enumerate() {
                 {byte}
                 if i == 31 {
-                    {U256::transform_limbsize(8, 4)}
+                    {U256::transform_limbsize(BYTE_LIMB_BITS, NIBBLE_LIMB_BITS)}
                 }
             }
         }
@@ -508,12 +879,108 @@ pub fn stacksat128_push_message_script(message_bytes: &[u8]) -> Script {
     }
 }
 
+/// Like `stacksat128_push_message_script`, but reads the message from `reader`
+/// until EOF, 32 bytes at a time, so only the generated script is held in
+/// memory. Reads interrupted by `ErrorKind::Interrupted` are retried; any other
+/// read error is returned. Panics like the slice version as soon as the message
+/// passes `MAX_MESSAGE_BYTES`.
+pub fn stacksat128_push_message_script_from_reader<R: std::io::Read>(
+    mut reader: R,
+) -> std::io::Result<Script> {
+    let mut chunk_scripts = Vec::new();
+    let mut message_len = 0;
+    loop {
+        let mut chunk = [0u8; 32];
+        let n = read_chunk(&mut reader, &mut chunk)?;
+        if n == 0 {
+            break;
+        }
+        message_len += n;
+        assert_message_len(message_len);
+        chunk_scripts.push(script! {
+            for byte in chunk {
+                {byte}
+            }
+            {U256::transform_limbsize(BYTE_LIMB_BITS, NIBBLE_LIMB_BITS)}
+        });
+        if n < chunk.len() {
+            break;
+        }
+    }
+    let needed_padding_nibbles = 2 * (message_len.next_multiple_of(32) - message_len);
+
+    Ok(script! {
+        for chunk_script in chunk_scripts {
+            { chunk_script }
+        }
+        for _ in 0..needed_padding_nibbles {
+            OP_DROP
+        }
+    })
+}
+
+/// Pushes `message_bytes` byte by byte in order, each byte's nibbles in
+/// `order`. `LsbFirst` lays the message out as an LSB-first encoder would;
+/// follow it with `stacksat128_reorder_nibbles_script` before computing.
+pub fn stacksat128_push_message_script_with_order(
+    message_bytes: &[u8],
+    order: NibbleOrder,
+) -> Script {
+    match order {
+        NibbleOrder::MsbFirst => stacksat128_push_message_script(message_bytes),
+        NibbleOrder::LsbFirst => {
+            assert_message_len(message_bytes.len());
+            script! {
+                for byte in message_bytes.iter() {
+                    { (byte & 0xF) as u32 }
+                    { (byte >> 4) as u32 }
+                }
+            }
+        }
+    }
+}
+
+/// Converts a `message_len`-byte message on top of the stack from `order` to
+/// the canonical `MsbFirst` layout the compute script expects. Empty for
+/// `MsbFirst`; for `LsbFirst` each byte's pair is rolled up from the bottom of
+/// the message and swapped, leaving the bytes in their original order.
+pub fn stacksat128_reorder_nibbles_script(message_len: usize, order: NibbleOrder) -> Script {
+    assert_message_len(message_len);
+    let deepest = (2 * message_len).saturating_sub(1) as u32;
+    script! {
+        if order == NibbleOrder::LsbFirst {
+            for _ in 0..message_len {
+                { deepest } OP_ROLL
+                { deepest } OP_ROLL
+                OP_SWAP
+            }
+        }
+    }
+}
+
+/// Byte order of a digest handed to `stacksat128_verify_output_script_with_order`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ByteOrder {
+    /// Canonical order, as returned by `stacksat128::stacksat_hash` and
+    /// printed by `hex::encode`: byte 0 first.
+    BigEndian,
+    /// Byte-reversed, as produced by libraries that store or display hashes
+    /// the way Bitcoin shows txids.
+    LittleEndian,
+}
+
+/// Checks the 64 digest nibbles the compute script leaves on the stack against
+/// `expected_output`, leaving `OP_TRUE` on success. `expected_output` is in the
+/// canonical byte order of `stacksat128::stacksat_hash`; on the stack each byte
+/// becomes its high nibble then its low nibble (the `transform_limbsize(8, 4)`
+/// layout), so byte 0's high nibble is deepest. For reversed digests use
+/// `stacksat128_verify_output_script_with_order`.
 pub fn stacksat128_verify_output_script(expected_output: [u8; 32]) -> Script {
     script! {
         for (i, byte) in expected_output.into_iter().enumerate() {
             {byte}
             if i % 32 == 31 {
-                {U256::transform_limbsize(8,4)}
+                {U256::transform_limbsize(BYTE_LIMB_BITS, NIBBLE_LIMB_BITS)}
             }
         }
 
@@ -526,80 +993,944 @@ pub fn stacksat128_verify_output_script(expected_output: [u8; 32]) -> Script {
     }
 }
 
-fn chunk_message(message_bytes: &[u8]) -> Vec<[u8; 32]> {
-    let len = message_bytes.len();
-    let needed_padding_bytes = if len % 32 == 0 { 0 } else { 32 - (len % 32) };
-
-    message_bytes
-        .iter()
-        .copied()
-        .chain(std::iter::repeat(0u8).take(needed_padding_bytes))
-        .collect::<Vec<u8>>()
-        .chunks(32)
-        .map(|chunk| {
-            let mut arr = [0u8; 32];
-            arr[..chunk.len()].copy_from_slice(chunk);
-            arr
-        })
-        .collect()
+/// `stacksat128_verify_output_script` for a digest given in `order`; a
+/// `LittleEndian` digest is reversed into the canonical order first.
+pub fn stacksat128_verify_output_script_with_order(digest: [u8; 32], order: ByteOrder) -> Script {
+    let mut expected_output = digest;
+    if order == ByteOrder::LittleEndian {
+        expected_output.reverse();
+    }
+    stacksat128_verify_output_script(expected_output)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use bitcoin::script::ScriptBuf;
-    use bitvm::execute_script_buf;
-
-    #[test]
-    fn test_optimized_empty_message() {
-        let expected_hash = <[u8; 32]>::from_hex(STACKSATSCRIPT_EMPTY_MSG_HASH).unwrap();
+/// Like `stacksat128_verify_output_script`, but only checks the first `n_bytes`
+/// of the digest against `expected_prefix` and drops the rest.
+///
+/// A truncated check only gives `8 * n_bytes` bits of preimage resistance and
+/// `4 * n_bytes` bits of collision resistance, e.g. a 16-byte prefix gives 128
+/// and 64 bits respectively. Only use it where that margin is acceptable.
+pub fn stacksat128_verify_truncated_script(expected_prefix: &[u8], n_bytes: usize) -> Script {
+    assert!(
+        (1..=32).contains(&n_bytes),
+        "STACKSAT-128: truncated digest must be 1 to 32 bytes, got {}",
+        n_bytes
+    );
+    assert!(
+        expected_prefix.len() >= n_bytes,
+        "STACKSAT-128: expected prefix has {} bytes, need {}",
+        expected_prefix.len(),
+        n_bytes
+    );
+    let n_nibbles = n_bytes * 2;
 
-        let compute_script = stacksat128_compute_script_optimized(0);
-        let compute_script_size = compute_script.clone().compile().to_bytes().len();
-        let verify_script = stacksat128_verify_output_script(expected_hash);
+    script! {
+        // The trailing digest nibbles sit on top of the stack
+        { generate_drop_script(STACKSATSCRIPT_STATE_NIBBLES - n_nibbles) }
 
-        let mut script_bytes = compute_script.compile().to_bytes();
-        script_bytes.extend(verify_script.compile().to_bytes());
+        for byte in expected_prefix[..n_bytes].iter() {
+            { (byte >> 4) as u32 }
+            { (byte & 0xF) as u32 }
+        }
 
-        let script = ScriptBuf::from_bytes(script_bytes);
-        let result = execute_script_buf(script);
+        for i in (2..n_nibbles + 1).rev() {
+            {i}
+            OP_ROLL
+            OP_EQUALVERIFY
+        }
+        OP_EQUAL
+    }
+}
 
-        println!(
-            "Optimized empty message test: {}",
-            if result.success { "SUCCESS" } else { "FAILED" }
-        );
-        println!("Optimized script size: {} bytes", compute_script_size);
+/// Pushes the all-zero initial state for `stacksat128_absorb_block_script`.
+pub fn stacksat128_init_state_script() -> Script {
+    generate_push_script(0, STACKSATSCRIPT_STATE_NIBBLES)
+}
 
-        if !result.success {
-            println!("Error: {:?}", result.error);
-            println!("Final Stack: {:?}", result.final_stack);
+/// Absorbs one rate block into the state, without the permutation. Expects the
+/// 64 state nibbles (state nibble 0 deepest) with the block's 32 nibbles on top
+/// (block nibble 31 topmost), and leaves the 64 absorbed state nibbles in the
+/// same order, ready for the rounds.
+///
+/// The script is assembled on a `StackTracker` that holds the state and block
+/// as variables, so it fails to generate if the absorption doesn't consume
+/// exactly those 96 elements.
+pub fn stacksat128_absorb_rate_block_script() -> Script {
+    let mut stack = StackTracker::new();
+    stack.define(STACKSATSCRIPT_STATE_NIBBLES as u32, "absorb_state");
+    stack.define(STACKSATSCRIPT_RATE_NIBBLES as u32, "absorb_block");
+    let absorb = script! {
+        // Move the block to the altstack, nibble 0 on top, as the absorption expects
+        for _ in 0..STACKSATSCRIPT_RATE_NIBBLES {
+            OP_TOALTSTACK
         }
+        { generate_optimized_absorption() }
+    };
+    stack.custom(absorb, 2, false, 0, "absorb_rate_block");
+    stack.define(STACKSATSCRIPT_STATE_NIBBLES as u32, "absorbed_state");
+    stack.get_script()
+}
 
-        assert!(result.success, "Optimized empty message test failed");
+/// One sponge step, for hashing a message across several scripts. Expects the
+/// 64 state nibbles with the block's 32 nibbles on top (block nibble 31
+/// topmost) and leaves the next state in their place: absorb, then 16 rounds.
+///
+/// Start from `stacksat128_init_state_script` and feed the zero-padded message
+/// block by block (see `stacksat128::padding`); the state after the last block
+/// is the digest, so `stacksat128_verify_output_script` finalizes.
+pub fn stacksat128_absorb_block_script() -> Script {
+    script! {
+        { stacksat128_absorb_rate_block_script() }
+        for round_idx in 0..STACKSATSCRIPT_ROUNDS {
+            { generate_optimized_round(round_idx) }
+        }
     }
+}
 
-    #[test]
-    fn test_size_comparison() {
-        println!("=== OPTIMIZATION IMPACT ANALYSIS ===");
+/// How finely `stacksat128_step_scripts` splits the work on one block.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StepGranularity {
+    /// One step per block: `stacksat128_absorb_block_script`.
+    #[default]
+    Block,
+    /// One step for the absorption, then one per round, so each step is about
+    /// a sixteenth of a block script and can be challenged on its own.
+    Round,
+}
 
-        // Test different message sizes to see optimization impact
-        for msg_len in [0, 15, 32, 64] {
-            let optimized_script = stacksat128_compute_script_optimized(msg_len);
-            let optimized_size = optimized_script.compile().to_bytes().len();
+/// The scripts that together process one block, in execution order. Every
+/// step takes its input state as the 64 nibbles on the stack (state nibble 0
+/// deepest) and leaves its output state the same way; the first step also
+/// expects the block's 32 nibbles on top of the state, as
+/// `stacksat128_absorb_block_script` does. Running the steps back to back
+/// equals that script, and with `Round` the intermediate states are those of
+/// `stacksat128::stacksat_hash_trace`.
+///
+/// Repeat the steps for every padded block, starting from
+/// `stacksat128_init_state_script`, to hash a message.
+pub fn stacksat128_step_scripts(granularity: StepGranularity) -> Vec<Script> {
+    match granularity {
+        StepGranularity::Block => vec![stacksat128_absorb_block_script()],
+        StepGranularity::Round => {
+            let mut steps = vec![stacksat128_absorb_rate_block_script()];
+            steps.extend((0..STACKSATSCRIPT_ROUNDS).map(generate_optimized_round));
+            steps
+        }
+    }
+}
 
-            println!("Message length: {} bytes", msg_len);
-            println!("Optimized script size: {} bytes", optimized_size);
+/// Compute script followed by an XOF squeeze: leaves the first `out_nibbles`
+/// nibbles of the `Stacksat128::finalize_xof` stream on the stack, first nibble
+/// deepest. The first 64 are the standard digest; each further block of 64 is
+/// a copy of the previous block run through the 16-round permutation. Keep
+/// `out_nibbles` well under the 1000-element stack limit.
+pub fn stacksat128_xof_script(message_len: usize, out_nibbles: usize) -> Script {
+    let blocks = out_nibbles.div_ceil(STACKSATSCRIPT_STATE_NIBBLES).max(1);
+    let round_scripts: Vec<Script> = (0..STACKSATSCRIPT_ROUNDS)
+        .map(generate_optimized_round)
+        .collect();
 
-            if optimized_size < 10000 {
-                println!("✓ Target achieved for {} byte messages", msg_len);
-            } else {
-                println!("✗ Target not yet achieved for {} byte messages", msg_len);
+    script! {
+        { stacksat128_compute_script_optimized(message_len) }
+        for _ in 1..blocks {
+            // Copy the last output block and permute the copy
+            for _ in 0..STACKSATSCRIPT_STATE_NIBBLES {
+                { STACKSATSCRIPT_STATE_NIBBLES - 1 } OP_PICK
+            }
+            for round_script in round_scripts.iter() {
+                { round_script.clone() }
             }
-            println!();
         }
+        // Trim the last block down to the requested length
+        { generate_drop_script(blocks * STACKSATSCRIPT_STATE_NIBBLES - out_nibbles) }
     }
+}
 
-    #[test]
+/// Compute script with `message_bytes` embedded: pushes the message as nibbles
+/// (high then low per byte) and hashes it, leaving the 64 digest nibbles on the
+/// stack like `push + compute`. Pushing nibbles directly skips the byte-to-nibble
+/// conversion and padding drops of `stacksat128_push_message_script`.
+pub fn stacksat128_compute_with_message_script(message_bytes: &[u8]) -> Script {
+    script! {
+        for byte in message_bytes.iter() {
+            { (byte >> 4) as u32 }
+            { (byte & 0xF) as u32 }
+        }
+        { stacksat128_compute_script_optimized(message_bytes.len()) }
+    }
+}
+
+/// Hash-check script for `message_bytes`: computes STACKSAT-128 over a message
+/// of the same length supplied as witness (see `stacksat128_push_message_script`)
+/// and verifies the result against the message's digest.
+pub fn stacksat128_full_script(message_bytes: &[u8]) -> Script {
+    let expected = stacksat128::stacksat_hash(message_bytes);
+    script! {
+        { stacksat128_compute_script_optimized(message_bytes.len()) }
+        { stacksat128_verify_output_script(expected) }
+    }
+}
+
+/// Preimage-check gadget: accepts exactly the `message_len`-byte messages that
+/// hash to `expected_digest`. The witness is the message as `2 * message_len`
+/// nibbles, high nibble of the first byte pushed first; the digest is embedded
+/// as plain bytes and rearranged into the script's internal nibble order here.
+/// The script length depends on `message_len`, so it is a parameter.
+pub fn stacksat128_preimage_check_script(message_len: usize, expected_digest: [u8; 32]) -> Script {
+    script! {
+        { stacksat128_compute_script_optimized(message_len) }
+        { stacksat128_verify_output_script(expected_digest) }
+    }
+}
+
+/// Tapscript spending condition "reveal a preimage of `expected_digest` and
+/// sign with `pubkey`": a BIP340 signature check followed by
+/// `stacksat128_preimage_check_script`. The witness is the message nibbles as
+/// for the preimage check, with the Schnorr signature pushed last, on top. The
+/// signature is checked first so that a spend without it fails before hashing.
+pub fn stacksat128_preimage_and_sig_script(
+    message_len: usize,
+    expected_digest: [u8; 32],
+    pubkey: &bitcoin::XOnlyPublicKey,
+) -> Script {
+    script! {
+        { *pubkey }
+        OP_CHECKSIGVERIFY
+        { stacksat128_preimage_check_script(message_len, expected_digest) }
+    }
+}
+
+/// Merkle inclusion gadget for trees built by `stacksat128::merkle`: accepts a
+/// leaf and a `depth`-level authentication path that hash up to `root`, using
+/// the same tagged leaf and node hashes as `stacksat128::merkle::verify_proof`.
+///
+/// The witness (see `stacksat128_merkle_witness_script`) is, from the bottom:
+/// for each level from the root down, the sibling's 64 nibbles and a direction
+/// bit (1 if the path node is the right child), then the leaf's 64 nibbles on
+/// top. The bits only order each pair, so the gadget proves membership, not a
+/// particular index. Each level keeps 65 witness elements on the stack, so keep
+/// `depth` well under the 1000-element stack limit.
+pub fn stacksat128_merkle_inclusion_script(root: [u8; 32], depth: usize) -> Script {
+    let node_nibbles = STACKSATSCRIPT_STATE_NIBBLES;
+    let leaf_hash = stacksat128_compute_script_optimized(1 + 32);
+    let node_hash = stacksat128_compute_script_optimized(1 + 2 * 32);
+
+    script! {
+        // Leaf hash H(0x00 || leaf): slide the tag nibbles under the leaf
+        0 0
+        for _ in 0..node_nibbles {
+            { node_nibbles + 1 } OP_ROLL
+        }
+        { leaf_hash }
+
+        for _ in 0..depth {
+            // Bring the direction bit above the node
+            { node_nibbles } OP_ROLL
+            OP_NOTIF
+                // The node is the left child: move the sibling above it
+                for _ in 0..node_nibbles {
+                    { 2 * node_nibbles - 1 } OP_ROLL
+                }
+            OP_ENDIF
+
+            // Node hash H(0x01 || left || right)
+            0 1
+            for _ in 0..2 * node_nibbles {
+                { 2 * node_nibbles + 1 } OP_ROLL
+            }
+            { node_hash.clone() }
+        }
+
+        { stacksat128_verify_output_script(root) }
+    }
+}
+
+/// Witness for `stacksat128_merkle_inclusion_script` from a
+/// `stacksat128::merkle::merkle_proof`: the siblings and the bits of `index`,
+/// root level first, then `leaf`, every value as nibbles (high then low per byte).
+pub fn stacksat128_merkle_witness_script(
+    leaf: [u8; 32],
+    index: usize,
+    proof: &[[u8; 32]],
+) -> Script {
+    script! {
+        for (level, sibling) in proof.iter().enumerate().rev() {
+            for byte in sibling.iter() {
+                { (byte >> 4) as u32 }
+                { (byte & 0xF) as u32 }
+            }
+            { ((index >> level) & 1) as u32 }
+        }
+        for byte in leaf.iter() {
+            { (byte >> 4) as u32 }
+            { (byte & 0xF) as u32 }
+        }
+    }
+}
+
+/// Wraps `stacksat128_full_script` into a tapscript leaf, returning the leaf hash
+/// and the compiled leaf script.
+#[cfg(feature = "taproot")]
+pub fn stacksat128_tapleaf(
+    message_bytes: &[u8],
+) -> (bitcoin::taproot::TapLeafHash, bitcoin::ScriptBuf) {
+    use bitcoin::taproot::{LeafVersion, TapLeafHash};
+
+    let leaf_script =
+        bitcoin::ScriptBuf::from_bytes(stacksat128_full_script(message_bytes).compile().to_bytes());
+    let leaf_hash = TapLeafHash::from_script(&leaf_script, LeafVersion::TapScript);
+    (leaf_hash, leaf_script)
+}
+
+/// What `ScriptBuilder` does with a message too long for its scripts.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OversizePolicy {
+    /// Panic on messages longer than `MAX_MESSAGE_BYTES`, like the free
+    /// generator functions.
+    #[default]
+    Panic,
+    /// Return `MessageTooLong` for messages longer than `MAX_MESSAGE_BYTES`.
+    Error,
+    /// Keep the first `MAX_EXECUTABLE_MESSAGE_BYTES` bytes of any longer
+    /// message, so the script still fits the stack limit. Nothing is printed;
+    /// `ScriptBuilder::effective_len` tells the caller whether a message was
+    /// cut. This changes the committed message: the script hashes and checks the
+    /// truncated prefix, so every message sharing that prefix passes it. Only
+    /// use it where the tail is known not to matter.
+    Truncate,
+}
+
+/// A message longer than `MAX_MESSAGE_BYTES`, rejected under
+/// `OversizePolicy::Error`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MessageTooLong {
+    /// Length of the rejected message, in bytes.
+    pub len: usize,
+}
+
+impl core::fmt::Display for MessageTooLong {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "message of {} bytes exceeds the {}-byte limit",
+            self.len, MAX_MESSAGE_BYTES
+        )
+    }
+}
+
+impl std::error::Error for MessageTooLong {}
+
+/// Script generator with configurable handling of oversized messages and of
+/// the S-box realization. The default builder behaves like the free functions.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ScriptBuilder {
+    oversize: OversizePolicy,
+    sbox: SboxStrategy,
+}
+
+impl ScriptBuilder {
+    /// Builder with the defaults: `OversizePolicy::Panic`, `SboxStrategy::Table`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set what happens to messages longer than `MAX_MESSAGE_BYTES`.
+    pub fn oversize_policy(mut self, policy: OversizePolicy) -> Self {
+        self.oversize = policy;
+        self
+    }
+
+    /// Set how the compute script realizes the S-box.
+    pub fn sbox_strategy(mut self, sbox: SboxStrategy) -> Self {
+        self.sbox = sbox;
+        self
+    }
+
+    /// Number of bytes of a `len`-byte message the generated scripts cover
+    /// under the oversize policy. Less than `len` means the message was
+    /// truncated.
+    ///
+    /// # Panics
+    ///
+    /// Under `OversizePolicy::Panic`, if `len` exceeds `MAX_MESSAGE_BYTES`.
+    pub fn effective_len(&self, len: usize) -> Result<usize, MessageTooLong> {
+        match self.oversize {
+            OversizePolicy::Truncate => Ok(len.min(MAX_EXECUTABLE_MESSAGE_BYTES)),
+            _ if len <= MAX_MESSAGE_BYTES => Ok(len),
+            OversizePolicy::Panic => panic!(
+                "STACKSAT-128: Message length {} > {} bytes not supported",
+                len, MAX_MESSAGE_BYTES
+            ),
+            OversizePolicy::Error => Err(MessageTooLong { len }),
+        }
+    }
+
+    /// Compute script for a `message_len`-byte message; see
+    /// `stacksat128_compute_script_with_sbox`.
+    ///
+    /// # Panics
+    ///
+    /// Under `OversizePolicy::Panic`, if `message_len` exceeds
+    /// `MAX_MESSAGE_BYTES`.
+    pub fn compute_script(&self, message_len: usize) -> Result<Script, MessageTooLong> {
+        let len = self.effective_len(message_len)?;
+        Ok(stacksat128_compute_script_with_sbox(len, self.sbox))
+    }
+
+    /// Witness pushing `message`; see `stacksat128_push_message_script`.
+    ///
+    /// # Panics
+    ///
+    /// Under `OversizePolicy::Panic`, if `message` is longer than
+    /// `MAX_MESSAGE_BYTES`.
+    pub fn push_message_script(&self, message: &[u8]) -> Result<Script, MessageTooLong> {
+        let len = self.effective_len(message.len())?;
+        Ok(stacksat128_push_message_script(&message[..len]))
+    }
+
+    /// Hash-check script for `message`; see `stacksat128_full_script`.
+    ///
+    /// # Panics
+    ///
+    /// Under `OversizePolicy::Panic`, if `message` is longer than
+    /// `MAX_MESSAGE_BYTES`.
+    pub fn full_script(&self, message: &[u8]) -> Result<Script, MessageTooLong> {
+        let len = self.effective_len(message.len())?;
+        let expected = stacksat128::stacksat_hash(&message[..len]);
+        Ok(script! {
+            { stacksat128_compute_script_with_sbox(len, self.sbox) }
+            { stacksat128_verify_output_script(expected) }
+        })
+    }
+}
+
+/// Why a script run by `run_script_checked` failed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScriptExecError {
+    /// The interpreter error, or `None` if the script ran to completion but
+    /// left a false or unclean stack.
+    pub error: Option<String>,
+    /// The last opcode executed before the script stopped.
+    pub last_opcode: Option<Opcode>,
+    /// The main stack when the script stopped.
+    pub final_stack: String,
+}
+
+impl core::fmt::Display for ScriptExecError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match &self.error {
+            Some(error) => write!(f, "script execution failed: {}", error)?,
+            None => write!(f, "script execution failed: false or unclean final stack")?,
+        }
+        if let Some(opcode) = self.last_opcode {
+            write!(f, " (last opcode {})", opcode)?;
+        }
+        write!(f, "; final stack: {}", self.final_stack)
+    }
+}
+
+impl std::error::Error for ScriptExecError {}
+
+/// Execute `script` and report a failure as a `ScriptExecError`. Success means
+/// a clean stack: exactly one element, and true. A gadget that leaves residue
+/// under its result fails here even if the interpreter would accept it.
+pub fn run_script_checked(script: bitcoin::ScriptBuf) -> Result<(), ScriptExecError> {
+    let result = execute_script_buf(script);
+    if result.success && result.final_stack.len() == 1 {
+        Ok(())
+    } else {
+        Err(ScriptExecError {
+            error: result.error.map(|error| format!("{:?}", error)),
+            last_opcode: result.last_opcode,
+            final_stack: format!("{:?}", result.final_stack),
+        })
+    }
+}
+
+// Fill `chunk` from `reader`, stopping early only at EOF. Returns the number
+// of bytes read.
+fn read_chunk<R: std::io::Read>(reader: &mut R, chunk: &mut [u8; 32]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < chunk.len() {
+        match reader.read(&mut chunk[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(filled)
+}
+
+fn chunk_message(message_bytes: &[u8]) -> Vec<[u8; 32]> {
+    let len = message_bytes.len();
+    let needed_padding_bytes = if len % 32 == 0 { 0 } else { 32 - (len % 32) };
+
+    message_bytes
+        .iter()
+        .copied()
+        .chain(std::iter::repeat(0u8).take(needed_padding_bytes))
+        .collect::<Vec<u8>>()
+        .chunks(32)
+        .map(|chunk| {
+            let mut arr = [0u8; 32];
+            arr[..chunk.len()].copy_from_slice(chunk);
+            arr
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::script::ScriptBuf;
+
+    #[test]
+    fn test_optimized_empty_message() {
+        let expected_hash = <[u8; 32]>::from_hex(STACKSATSCRIPT_EMPTY_MSG_HASH).unwrap();
+
+        let compute_script = stacksat128_compute_script_optimized(0);
+        let compute_script_size = compute_script.clone().compile().to_bytes().len();
+        let verify_script = stacksat128_verify_output_script(expected_hash);
+
+        let mut script_bytes = compute_script.compile().to_bytes();
+        script_bytes.extend(verify_script.compile().to_bytes());
+
+        println!("Optimized script size: {} bytes", compute_script_size);
+
+        if let Err(err) = run_script_checked(ScriptBuf::from_bytes(script_bytes)) {
+            panic!("Optimized empty message test failed: {}", err);
+        }
+    }
+
+    #[test]
+    fn test_script_asm_sections() {
+        let asm = stacksat128_script_asm(4);
+        assert!(!asm.is_empty());
+        for marker in [
+            "# optimized_padding",
+            "# optimized_state_init",
+            "# opt_absorb_0",
+            "# opt_round_0_0",
+            "# opt_round_0_15",
+        ] {
+            assert!(asm.contains(marker), "missing section marker {}", marker);
+        }
+        assert!(!asm.contains("# opt_absorb_1"));
+        assert!(asm.contains("OP_ROLL"));
+
+        let empty_asm = stacksat128_script_asm(0);
+        assert!(empty_asm.starts_with("# optimized_empty_hash\n"));
+    }
+
+    #[test]
+    fn test_script_fragments() {
+        for len in [0usize, 4, 40] {
+            let fragments = stacksat128_script_fragments(len);
+            let mut concatenated = Vec::new();
+            for (label, fragment) in fragments.iter() {
+                let bytes = fragment.clone().compile().to_bytes();
+                if let Some(round) = label.strip_prefix("opt_round_") {
+                    let round_idx: usize = round.split('_').nth(1).unwrap().parse().unwrap();
+                    assert_eq!(
+                        bytes,
+                        stacksat128_round_script(round_idx).compile().to_bytes(),
+                        "{}",
+                        label
+                    );
+                }
+                concatenated.extend(bytes);
+            }
+            assert_eq!(
+                concatenated,
+                stacksat128_compute_script_optimized(len)
+                    .compile()
+                    .to_bytes(),
+                "length {}",
+                len
+            );
+        }
+        assert!(std::panic::catch_unwind(|| stacksat128_round_script(16)).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "taproot")]
+    fn test_tapleaf() {
+        use bitcoin::taproot::{LeafVersion, TapLeafHash};
+
+        let message = b"test";
+        let (leaf_hash, leaf_script) = stacksat128_tapleaf(message);
+        let (again_hash, again_script) = stacksat128_tapleaf(message);
+        assert_eq!(leaf_hash, again_hash, "leaf hash must be stable");
+        assert_eq!(leaf_script, again_script);
+        assert_eq!(
+            leaf_hash,
+            TapLeafHash::from_script(&leaf_script, LeafVersion::TapScript)
+        );
+        assert_ne!(leaf_hash, stacksat128_tapleaf(b"tesu").0);
+
+        // The leaf script must accept the message as witness.
+        let mut script_bytes = stacksat128_push_message_script(message)
+            .compile()
+            .to_bytes();
+        script_bytes.extend(leaf_script.to_bytes());
+        if let Err(err) = run_script_checked(ScriptBuf::from_bytes(script_bytes)) {
+            panic!("tapleaf script rejected its preimage: {}", err);
+        }
+    }
+
+    #[test]
+    fn test_compute_script_rounds() {
+        for msg_len in [1, 32, 33] {
+            let full = stacksat128_compute_script_rounds(msg_len, 16)
+                .compile()
+                .to_bytes();
+            let standard = stacksat128_compute_script_optimized(msg_len)
+                .compile()
+                .to_bytes();
+            assert_eq!(full, standard, "rounds=16 must be the standard script");
+
+            let reduced = stacksat128_compute_script_rounds(msg_len, 8)
+                .compile()
+                .to_bytes();
+            assert!(reduced.len() < full.len());
+        }
+
+        // Same bounds as the core's StacksatBuilder::rounds, the reference
+        for rounds in [0, STACKSATSCRIPT_ROUNDS + 1] {
+            let rejected =
+                std::panic::catch_unwind(|| stacksat128_compute_script_rounds(1, rounds));
+            assert!(rejected.is_err(), "{} rounds accepted", rounds);
+        }
+    }
+
+    #[test]
+    fn test_known_vectors() {
+        assert_eq!(
+            STACKSATSCRIPT_EMPTY_MSG_HASH,
+            stacksat128::TEST_VECTORS[0].1,
+            "empty-message constant diverged from the core vectors"
+        );
+
+        for (message, expected_hex) in stacksat128::TEST_VECTORS.iter() {
+            let expected_hash = <[u8; 32]>::from_hex(expected_hex).unwrap();
+
+            let mut script_bytes = stacksat128_push_message_script(message)
+                .compile()
+                .to_bytes();
+            script_bytes.extend(
+                stacksat128_compute_script_optimized(message.len())
+                    .compile()
+                    .to_bytes(),
+            );
+            script_bytes.extend(
+                stacksat128_verify_output_script(expected_hash)
+                    .compile()
+                    .to_bytes(),
+            );
+
+            if let Err(err) = run_script_checked(ScriptBuf::from_bytes(script_bytes)) {
+                panic!(
+                    "script rejected vector for message of {} bytes: {}",
+                    message.len(),
+                    err
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_sbox_strategies() {
+        // The layer maps every nibble value, wherever it sits in the state
+        let state: Vec<u8> = (0..STACKSATSCRIPT_STATE_NIBBLES)
+            .map(|i| (i * 5) as u8 & 0xF)
+            .collect();
+        for strategy in [SboxStrategy::Table, SboxStrategy::Conditional] {
+            let layer = script! {
+                for nibble in state.iter() {
+                    { *nibble as u32 }
+                }
+                { strategy.layer_script() }
+                for nibble in state.iter().rev() {
+                    { STACKSATSCRIPT_SBOX[*nibble as usize] }
+                    OP_EQUALVERIFY
+                }
+                OP_TRUE
+            };
+            assert_eq!(
+                run_script_checked(layer.compile()),
+                Ok(()),
+                "{:?}",
+                strategy
+            );
+
+            for (message, expected_hex) in stacksat128::TEST_VECTORS.iter() {
+                let script = script! {
+                    { stacksat128_push_message_script(message) }
+                    { stacksat128_compute_script_with_sbox(message.len(), strategy) }
+                    { stacksat128_verify_output_script(<[u8; 32]>::from_hex(expected_hex).unwrap()) }
+                };
+                if let Err(err) = run_script_checked(script.compile()) {
+                    panic!(
+                        "{:?} S-box, {}-byte vector: {}",
+                        strategy,
+                        message.len(),
+                        err
+                    );
+                }
+            }
+        }
+
+        assert_eq!(
+            stacksat128_compute_script_with_sbox(32, SboxStrategy::Table)
+                .compile()
+                .to_bytes(),
+            stacksat128_compute_script_optimized(32)
+                .compile()
+                .to_bytes()
+        );
+
+        let table = stacksat128_sbox_stats(SboxStrategy::Table);
+        let conditional = stacksat128_sbox_stats(SboxStrategy::Conditional);
+        println!("table: {:?}, conditional: {:?}", table, conditional);
+        assert!(conditional.script_size > table.script_size);
+        assert!(conditional.extra_stack_items < table.extra_stack_items);
+    }
+
+    #[test]
+    fn test_opcode_histogram() {
+        let histogram = stacksat128_opcode_histogram(32);
+        for (opcode, count) in histogram.iter() {
+            println!("{:<20} {}", opcode, count);
+        }
+        assert!(histogram.get("OP_PICK").copied().unwrap_or(0) > 0);
+        assert!(histogram.get("OP_ROLL").copied().unwrap_or(0) > 0);
+        assert!(histogram.get("OP_ADD").copied().unwrap_or(0) > 0);
+    }
+
+    #[test]
+    fn test_execution_cost() {
+        let costs: Vec<u64> = [0, 16, 32, 64]
+            .iter()
+            .map(|&len| stacksat128_execution_cost(len))
+            .collect();
+        println!("execution cost by length 0/16/32/64: {:?}", costs);
+        assert!(
+            costs.windows(2).all(|pair| pair[0] < pair[1]),
+            "{:?}",
+            costs
+        );
+
+        // The rounds carry almost all of it: a 32-byte message runs two blocks
+        let rounds: u64 = (0..STACKSATSCRIPT_ROUNDS)
+            .map(|round_idx| weighted_cost(&opcode_histogram(stacksat128_round_script(round_idx))))
+            .sum();
+        assert!(
+            rounds * 2 * 10 > costs[2] * 9,
+            "rounds cost {} per block of {}",
+            rounds,
+            costs[2]
+        );
+    }
+
+    #[test]
+    fn test_cleanup_is_batched() {
+        // The only cleanup in the compute script is the per-round S-box table,
+        // dropped as 8 contiguous OP_2DROPs; the message is consumed from the
+        // altstack and the digest needs no trailing cleanup
+        for (len, blocks) in [(1usize, 1usize), (16, 1), (40, 3)] {
+            let histogram = stacksat128_opcode_histogram(len);
+            assert_eq!(
+                histogram.get("OP_2DROP").copied().unwrap_or(0),
+                8 * STACKSATSCRIPT_ROUNDS * blocks,
+                "length {}",
+                len
+            );
+            assert_eq!(histogram.get("OP_DROP"), None, "length {}", len);
+        }
+    }
+
+    #[test]
+    fn test_empty_message_honest() {
+        let expected_hash = <[u8; 32]>::from_hex(STACKSATSCRIPT_EMPTY_MSG_HASH).unwrap();
+        assert_eq!(expected_hash, stacksat128::stacksat_hash(&[]));
+
+        let compute_script = stacksat128_compute_script_empty_honest();
+        let fast_path_size = stacksat128_compute_script_optimized(0)
+            .compile()
+            .to_bytes()
+            .len();
+        let mut script_bytes = compute_script.compile().to_bytes();
+        assert!(script_bytes.len() > fast_path_size);
+        script_bytes.extend(
+            stacksat128_verify_output_script(expected_hash)
+                .compile()
+                .to_bytes(),
+        );
+
+        if let Err(err) = run_script_checked(ScriptBuf::from_bytes(script_bytes)) {
+            panic!(
+                "sponge over the empty message disagrees with the hardcoded digest: {}",
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn test_verify_truncated() {
+        let (message, expected_hex) = stacksat128::TEST_VECTORS[1];
+        let expected_hash = <[u8; 32]>::from_hex(expected_hex).unwrap();
+
+        let run = |prefix: &[u8]| {
+            let mut script_bytes = stacksat128_push_message_script(message)
+                .compile()
+                .to_bytes();
+            script_bytes.extend(
+                stacksat128_compute_script_optimized(message.len())
+                    .compile()
+                    .to_bytes(),
+            );
+            script_bytes.extend(
+                stacksat128_verify_truncated_script(prefix, 16)
+                    .compile()
+                    .to_bytes(),
+            );
+            run_script_checked(ScriptBuf::from_bytes(script_bytes)).is_ok()
+        };
+
+        assert!(run(&expected_hash[..16]), "16-byte prefix rejected");
+
+        // Bytes past the prefix are not checked
+        let mut tail_changed = expected_hash;
+        tail_changed[31] ^= 1;
+        assert!(run(&tail_changed));
+
+        let mut prefix_changed = expected_hash;
+        prefix_changed[15] ^= 1;
+        assert!(!run(&prefix_changed[..16]), "wrong prefix accepted");
+    }
+
+    /// Legacy (pre-tapscript) `MAX_SCRIPT_SIZE`. It is the size a standalone
+    /// chunk of the hash must stay under to be usable outside tapscript, e.g.
+    /// when the computation is split across several transactions.
+    const SCRIPT_SIZE_TARGET: usize = 10_000;
+
+    /// Compiled size of each labeled section of the compute script.
+    fn section_sizes(msg_len: usize) -> Vec<(String, usize)> {
+        stacksat128_sections(msg_len, STACKSATSCRIPT_ROUNDS, true, SboxStrategy::Table)
+            .into_iter()
+            .map(|(label, section)| (label, section.compile().to_bytes().len()))
+            .collect()
+    }
+
+    /// Size of block `block_idx`: its absorption plus its 16 rounds.
+    fn block_size(sizes: &[(String, usize)], block_idx: usize) -> usize {
+        let absorb = format!("opt_absorb_{}", block_idx);
+        let round_prefix = format!("opt_round_{}_", block_idx);
+        sizes
+            .iter()
+            .filter(|(label, _)| *label == absorb || label.starts_with(&round_prefix))
+            .map(|(_, size)| size)
+            .sum()
+    }
+
+    #[test]
+    fn test_size_comparison() {
+        println!("=== OPTIMIZATION IMPACT ANALYSIS ===");
+
+        // Test different message sizes to see optimization impact
+        for msg_len in [0, 15, 32, 64] {
+            let optimized_script = stacksat128_compute_script_optimized(msg_len);
+            let optimized_size = optimized_script.compile().to_bytes().len();
+
+            println!("Message length: {} bytes", msg_len);
+            println!("Optimized script size: {} bytes", optimized_size);
+            println!();
+        }
+
+        // The total grows with the message, so the budget is checked per block.
+        // A block is one absorption plus 16 rounds, and rounds dominate it
+        // (about 2.4k bytes each, most of it MixColumns), so a whole block
+        // misses the 10k target. Every round must fit it on its own, blocks
+        // must cost the same wherever they sit in the message, and a block
+        // must not grow past its baseline.
+        let sizes = section_sizes(32);
+        let first_block = block_size(&sizes, 0);
+        let second_block = block_size(&sizes, 1);
+        println!("Per-block script size: {} bytes", first_block);
+        assert_eq!(first_block, second_block, "block size depends on position");
+        let ceiling = BLOCK_SIZE_BASELINE * (1000 + SCRIPT_SIZE_MARGIN_PERMILLE) / 1000;
+        assert!(
+            first_block <= ceiling,
+            "block is {} bytes, baseline {} allows at most {}",
+            first_block,
+            BLOCK_SIZE_BASELINE,
+            ceiling
+        );
+
+        for (label, size) in sizes.iter().filter(|(label, _)| label.starts_with("opt_")) {
+            println!("{:<16} {} bytes", label, size);
+            assert!(
+                *size < SCRIPT_SIZE_TARGET,
+                "section {} is {} bytes, target is < {}",
+                label,
+                size,
+                SCRIPT_SIZE_TARGET
+            );
+        }
+    }
+
+    /// Compiled compute script sizes, in bytes, by message length. When a
+    /// change shrinks the script, lower these to the sizes printed by
+    /// `test_script_size_regression`; raise them only for a deliberate cost.
+    const SCRIPT_SIZE_BASELINES: [(usize, usize); 2] = [(32, 79_873), (64, 159_713)];
+
+    /// Compiled size of one block (absorption and 16 rounds), in bytes, as
+    /// printed by `test_size_comparison`. It matches the baselines above: 64
+    /// bytes cost two more blocks than 32, plus 64 more altstack moves.
+    const BLOCK_SIZE_BASELINE: usize = 39_888;
+
+    /// Growth over a baseline tolerated before the regression test fails, in
+    /// parts per thousand.
+    const SCRIPT_SIZE_MARGIN_PERMILLE: usize = 10;
+
+    #[test]
+    fn test_script_size_regression() {
+        for (msg_len, baseline) in SCRIPT_SIZE_BASELINES {
+            let size = stacksat128_compute_script_optimized(msg_len)
+                .compile()
+                .to_bytes()
+                .len();
+            println!(
+                "{:>3}-byte message: {} bytes (baseline {})",
+                msg_len, size, baseline
+            );
+            let limit = baseline + baseline * SCRIPT_SIZE_MARGIN_PERMILLE / 1000;
+            assert!(
+                size <= limit,
+                "{}-byte compute script is {} bytes, baseline {} allows at most {}",
+                msg_len,
+                size,
+                baseline,
+                limit
+            );
+        }
+
+        // The empty message pushes its precomputed digest instead of running
+        // a block, so it alone has to fit the standalone target
+        let empty = stacksat128_compute_script_optimized(0)
+            .compile()
+            .to_bytes()
+            .len();
+        println!("  0-byte message: {} bytes", empty);
+        assert!(
+            empty < SCRIPT_SIZE_TARGET,
+            "empty-message script is {} bytes, target is < {}",
+            empty,
+            SCRIPT_SIZE_TARGET
+        );
+    }
+
+    #[test]
     fn test_optimization_correctness() {
         println!("=== OPTIMIZATION CORRECTNESS TEST ===");
 
@@ -619,19 +1950,1075 @@ mod tests {
         script_bytes.extend(compute_script.compile().to_bytes());
         script_bytes.extend(verify_script.compile().to_bytes());
 
-        let script = ScriptBuf::from_bytes(script_bytes);
-        let result = execute_script_buf(script);
-        println!("result: {:?}", result.final_stack);
+        if let Err(err) = run_script_checked(ScriptBuf::from_bytes(script_bytes)) {
+            panic!("Optimization correctness test failed: {}", err);
+        }
+    }
+
+    /// Reference column mix: `y[r][c] = x[r][c] + x[r+1][c] + x[r+2][c] + x[r+3][c]`
+    /// (rows mod 8, sum mod 16), as in the core crate's round function.
+    fn reference_mixcolumns(
+        st: &[u8; STACKSATSCRIPT_STATE_NIBBLES],
+    ) -> [u8; STACKSATSCRIPT_STATE_NIBBLES] {
+        let mut out = [0u8; STACKSATSCRIPT_STATE_NIBBLES];
+        for r_idx in 0..8 {
+            for c_idx in 0..8 {
+                let sum: u8 = (0..4).map(|k| st[((r_idx + k) % 8) * 8 + c_idx]).sum();
+                out[r_idx * 8 + c_idx] = sum % 16;
+            }
+        }
+        out
+    }
 
-        println!(
-            "Correctness test: {}",
-            if result.success { "PASSED" } else { "FAILED" }
+    /// Replays the roll/pick decisions of `STACKATSCRIPT_MIXCOLUMN_DEPTHS` the way
+    /// `generate_optimized_mixcolumns` emits them, on a stack model whose last
+    /// element is the top. Nibble 63 starts on top.
+    fn simulate_mixcolumns(st: &[u8; STACKSATSCRIPT_STATE_NIBBLES]) -> Vec<u8> {
+        fn fetch(stack: &mut Vec<u8>, depth: usize, roll: bool) {
+            assert!(depth < stack.len(), "depth {} past the stack", depth);
+            let idx = stack.len() - 1 - depth;
+            let value = if roll { stack.remove(idx) } else { stack[idx] };
+            stack.push(value);
+        }
+        fn add(stack: &mut Vec<u8>) {
+            let b = stack.pop().unwrap();
+            let a = stack.pop().unwrap();
+            stack.push(a + b);
+        }
+
+        let mut stack = st.to_vec();
+        for info in STACKATSCRIPT_MIXCOLUMN_DEPTHS.iter() {
+            fetch(&mut stack, info.depths[0], info.will_remove[0]);
+            fetch(&mut stack, info.depths[1] + 1, info.will_remove[1]);
+            add(&mut stack);
+            fetch(&mut stack, info.depths[2] + 1, info.will_remove[2]);
+            fetch(&mut stack, info.depths[3] + 2, info.will_remove[3]);
+            add(&mut stack);
+            add(&mut stack);
+            let top = stack.last_mut().unwrap();
+            assert!(*top < 64, "sum out of range for generate_mod64_to_mod16");
+            *top %= 16;
+        }
+        stack
+    }
+
+    #[test]
+    fn test_parameters_match_core() {
+        assert_eq!(STACKSATSCRIPT_RATE_NIBBLES, 2 * stacksat128::rate_bytes());
+        assert_eq!(STACKSATSCRIPT_STATE_NIBBLES, stacksat128::state_bits() / 4);
+        assert_eq!(STACKSATSCRIPT_ROUNDS, stacksat128::rounds());
+        assert_eq!(STACKSATSCRIPT_STATE_NIBBLES, 2 * stacksat128::digest_size());
+    }
+
+    #[test]
+    fn test_permutation_matches_core() {
+        assert_eq!(STACKSATSCRIPT_FINAL_PERM, stacksat128::PERMUTATION);
+        assert_eq!(
+            STACKSATSCRIPT_INV_FINAL_PERM,
+            stacksat128::INVERSE_PERMUTATION
+        );
+    }
+
+    #[test]
+    fn test_mixcolumn_depths_match_reference() {
+        let mut seed = 0x2545_f491u32;
+        let mut states = vec![
+            [0u8; STACKSATSCRIPT_STATE_NIBBLES],
+            core::array::from_fn(|i| i as u8 % 16),
+            core::array::from_fn(|i| (i * 7 + 3) as u8 % 16),
+        ];
+        for _ in 0..8 {
+            states.push(core::array::from_fn(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                ((seed >> 16) & 0xF) as u8
+            }));
+        }
+
+        for st in states.iter() {
+            let simulated = simulate_mixcolumns(st);
+            let expected = reference_mixcolumns(st);
+            let diff: Vec<(usize, u8, u8)> = simulated
+                .iter()
+                .zip(expected.iter())
+                .enumerate()
+                .filter(|(_, (a, b))| a != b)
+                .map(|(i, (&a, &b))| (i, a, b))
+                .collect();
+            assert_eq!(simulated.len(), STACKSATSCRIPT_STATE_NIBBLES);
+            assert!(
+                diff.is_empty(),
+                "depth table diverges from reference, (nibble, simulated, reference): {:?}",
+                diff
+            );
+        }
+    }
+
+    // MixColumns as it was first written, re-wrapping the accumulated script
+    // once per position.
+    fn nested_mixcolumns() -> Script {
+        let mut mix_script = script!();
+
+        // For each position in the state
+        for r_idx in 0..8 {
+            for c_idx in 0..8 {
+                let position = r_idx * 8 + c_idx;
+
+                // Build script for this position
+                mix_script = script!(
+                    { mix_script }
+                    // Pick p0 to the top of the stack
+                    { STACKATSCRIPT_MIXCOLUMN_DEPTHS[position].depths[0] }
+                    // If the position will be removed, roll the stack, otherwise pick the value
+                    if STACKATSCRIPT_MIXCOLUMN_DEPTHS[position].will_remove[0] {
+                        OP_ROLL
+                    } else {
+                        OP_PICK
+                    }
+                    // Pick p1 to the top of the stack
+                    { STACKATSCRIPT_MIXCOLUMN_DEPTHS[position].depths[1] + 1 }
+                    // If the position will be removed, roll the stack, otherwise pick the value
+                    if STACKATSCRIPT_MIXCOLUMN_DEPTHS[position].will_remove[1] {
+                        OP_ROLL
+                    } else {
+                        OP_PICK
+                    }
+                    // p0 + p1
+                    OP_ADD
+
+                    // Pick p2 to the top of the stack
+                    { STACKATSCRIPT_MIXCOLUMN_DEPTHS[position].depths[2] + 1 }
+                    // If the position will be removed, roll the stack, otherwise pick the value
+                    if STACKATSCRIPT_MIXCOLUMN_DEPTHS[position].will_remove[2] {
+                        OP_ROLL
+                    } else {
+                        OP_PICK
+                    }
+                    // Pick p3 to the top of the stack
+                    { STACKATSCRIPT_MIXCOLUMN_DEPTHS[position].depths[3] + 2 }
+                    // If the position will be removed, roll the stack, otherwise pick the value
+                    if STACKATSCRIPT_MIXCOLUMN_DEPTHS[position].will_remove[3] {
+                        OP_ROLL
+                    } else {
+                        OP_PICK
+                    }
+                    // Then add p2 + p3
+                    OP_ADD
+
+                    // Finally add (p0+p1)+(p2+p3)
+                    OP_ADD
+                    // Mod 16
+                    { generate_mod64_to_mod16() }
+                );
+            }
+        }
+        mix_script
+    }
+
+    #[test]
+    fn test_mixcolumns_matches_nested_accumulation() {
+        assert_eq!(
+            generate_optimized_mixcolumns().compile().to_bytes(),
+            nested_mixcolumns().compile().to_bytes()
+        );
+    }
+
+    /// Run `script_sig` + `script_pubkey` as a legacy spend through Bitcoin
+    /// Core's consensus library.
+    #[cfg(feature = "consensus")]
+    fn consensus_verify(
+        script_sig: ScriptBuf,
+        script_pubkey: &ScriptBuf,
+    ) -> Result<(), bitcoin::consensus::validation::BitcoinconsensusError> {
+        use bitcoin::{
+            absolute::LockTime, transaction::Version, Amount, OutPoint, Sequence, Transaction,
+            TxIn, TxOut, Witness,
+        };
+
+        let spending_tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig,
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: Amount::ZERO,
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+        bitcoin::consensus::validation::verify_script(
+            script_pubkey,
+            0,
+            Amount::ZERO,
+            &bitcoin::consensus::serialize(&spending_tx),
+        )
+    }
+
+    /// Consensus limits found by running the scripts through libbitcoinconsensus,
+    /// which only evaluates legacy and segwit v0 scripts:
+    ///
+    /// - Legacy scripts are capped at 10,000 bytes and 201 non-push opcodes.
+    ///   One round alone is ~2.4k bytes with several hundred opcodes, so the
+    ///   compute script can only run in tapscript, which lifts both limits.
+    /// - Main and alt stack together may hold at most 1,000 elements, in
+    ///   tapscript too. The compute script parks every message nibble on the
+    ///   alt stack next to the 64-nibble state, so messages past
+    ///   `MAX_EXECUTABLE_MESSAGE_BYTES` exceed it.
+    /// - Every element is a single nibble, far below the 520-byte element limit.
+    #[test]
+    #[cfg(feature = "consensus")]
+    fn test_consensus_validation() {
+        let (message, expected_hex) = stacksat128::TEST_VECTORS[1];
+        let expected_hash = <[u8; 32]>::from_hex(expected_hex).unwrap();
+
+        // The digest check fits the legacy limits: push the digest nibbles as
+        // the scriptSig and check a 16-byte prefix in the scriptPubKey.
+        let digest_push = script! {
+            for byte in expected_hash {
+                { (byte >> 4) as u32 }
+                { (byte & 0xF) as u32 }
+            }
+        };
+        let script_sig = ScriptBuf::from_bytes(digest_push.compile().to_bytes());
+        let check = |prefix: &[u8]| {
+            ScriptBuf::from_bytes(
+                stacksat128_verify_truncated_script(prefix, 16)
+                    .compile()
+                    .to_bytes(),
+            )
+        };
+        assert!(consensus_verify(script_sig.clone(), &check(&expected_hash[..16])).is_ok());
+        let mut wrong = expected_hash;
+        wrong[0] ^= 1;
+        assert!(consensus_verify(script_sig, &check(&wrong[..16])).is_err());
+
+        // The full hash-check script is accepted by the BitVM interpreter but
+        // is far over the legacy size limit.
+        let full_script =
+            ScriptBuf::from_bytes(stacksat128_full_script(message).compile().to_bytes());
+        assert!(full_script.len() > 10_000);
+        let message_push = ScriptBuf::from_bytes(
+            stacksat128_push_message_script(message)
+                .compile()
+                .to_bytes(),
+        );
+        let mut script_bytes = message_push.to_bytes();
+        script_bytes.extend(full_script.to_bytes());
+        assert_eq!(
+            run_script_checked(ScriptBuf::from_bytes(script_bytes)),
+            Ok(())
+        );
+        assert!(consensus_verify(message_push, &full_script).is_err());
+    }
+
+    #[test]
+    fn test_compute_with_message_script() {
+        for len in [0usize, 3, 16, 40, 64] {
+            let message: Vec<u8> = (0..len).map(|i| (i * 53 + 7) as u8).collect();
+            let digest = stacksat128::stacksat_hash(&message);
+
+            let inlined = stacksat128_compute_with_message_script(&message)
+                .compile()
+                .to_bytes();
+            let mut separate = stacksat128_push_message_script(&message)
+                .compile()
+                .to_bytes();
+            separate.extend(
+                stacksat128_compute_script_optimized(len)
+                    .compile()
+                    .to_bytes(),
+            );
+            assert!(
+                inlined.len() <= separate.len(),
+                "length {}: inlined {} bytes, push + compute {} bytes",
+                len,
+                inlined.len(),
+                separate.len()
+            );
+
+            let verify = stacksat128_verify_output_script(digest)
+                .compile()
+                .to_bytes();
+            for compute in [inlined, separate] {
+                let mut script_bytes = compute;
+                script_bytes.extend_from_slice(&verify);
+                assert_eq!(
+                    run_script_checked(ScriptBuf::from_bytes(script_bytes)),
+                    Ok(()),
+                    "length {}",
+                    len
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_xof_script() {
+        for message in [&b""[..], b"abc", &[0x3C; 20]] {
+            let mut reader = stacksat128::Stacksat128::new();
+            reader.update(message);
+            let mut expected = [0u8; 48];
+            reader.finalize_xof().fill(&mut expected);
+            let expected_nibbles: Vec<u8> =
+                expected.iter().flat_map(|b| [b >> 4, b & 0xF]).collect();
+
+            // 96 nibbles: the digest plus half of the next block
+            let mut script_bytes = stacksat128_push_message_script(message)
+                .compile()
+                .to_bytes();
+            script_bytes.extend(
+                stacksat128_xof_script(message.len(), 96)
+                    .compile()
+                    .to_bytes(),
+            );
+            let check = script! {
+                for nibble in expected_nibbles.iter().rev() {
+                    { *nibble as u32 }
+                    OP_EQUALVERIFY
+                }
+                OP_TRUE
+            };
+            script_bytes.extend(check.compile().to_bytes());
+            if let Err(err) = run_script_checked(ScriptBuf::from_bytes(script_bytes)) {
+                panic!(
+                    "XOF script diverges for a {}-byte message: {}",
+                    message.len(),
+                    err
+                );
+            }
+        }
+
+        // Up to 64 nibbles is the standard compute script, trimmed
+        let mut standard = stacksat128_compute_script_optimized(5).compile().to_bytes();
+        assert_eq!(stacksat128_xof_script(5, 64).compile().to_bytes(), standard);
+        standard.extend(generate_drop_script(4).compile().to_bytes());
+        assert_eq!(stacksat128_xof_script(5, 60).compile().to_bytes(), standard);
+    }
+
+    #[test]
+    fn test_absorb_block_script() {
+        // Three blocks: two full ones and an 8-byte tail
+        let message: Vec<u8> = (0..40u8).map(|i| i.wrapping_mul(61) ^ 0x5A).collect();
+        let digest = stacksat128::stacksat_hash(&message);
+        let mut padded = message.clone();
+        padded.resize(
+            stacksat128::padding::padded_nibble_len(message.len()) / 2,
+            0,
+        );
+        let push_nibbles = |nibbles: &[u8]| {
+            script! {
+                for nibble in nibbles.iter() {
+                    { *nibble as u32 }
+                }
+            }
+        };
+        let to_nibbles =
+            |bytes: &[u8]| -> Vec<u8> { bytes.iter().flat_map(|b| [b >> 4, b & 0xF]).collect() };
+
+        // Carry the state across separate scripts, one block each, checking
+        // every intermediate state against the core block function
+        let mut state = [0u8; STACKSATSCRIPT_STATE_NIBBLES];
+        for block in padded.chunks_exact(16) {
+            let before = state;
+            stacksat128::stacksat_compress(&mut state, block.try_into().unwrap());
+            let step = script! {
+                { push_nibbles(&before) }
+                { push_nibbles(&to_nibbles(block)) }
+                { stacksat128_absorb_block_script() }
+                for nibble in state.iter().rev() {
+                    { *nibble as u32 }
+                    OP_EQUALVERIFY
+                }
+                OP_TRUE
+            };
+            if let Err(err) = run_script_checked(step.compile()) {
+                panic!("block step diverges from stacksat_compress: {}", err);
+            }
+        }
+
+        // Chained in one script, the steps equal the monolithic compute
+        let chained = script! {
+            { stacksat128_init_state_script() }
+            for block in padded.chunks_exact(16) {
+                { push_nibbles(&to_nibbles(block)) }
+                { stacksat128_absorb_block_script() }
+            }
+            { stacksat128_verify_output_script(digest) }
+        };
+        assert_eq!(run_script_checked(chained.compile()), Ok(()));
+    }
+
+    #[test]
+    fn test_round_steps() {
+        let message = b"one block of msg";
+        let digest = stacksat128::stacksat_hash(message);
+        let trace = stacksat128::stacksat_hash_trace(message);
+        let block: Vec<u8> = message.iter().flat_map(|b| [b >> 4, b & 0xF]).collect();
+        let push_nibbles = |nibbles: &[u8]| {
+            script! {
+                for nibble in nibbles.iter() {
+                    { *nibble as u32 }
+                }
+            }
+        };
+
+        let steps = stacksat128_step_scripts(StepGranularity::Round);
+        assert_eq!(steps.len(), 1 + STACKSATSCRIPT_ROUNDS);
+        assert_eq!(trace.len(), steps.len());
+        let block_size = stacksat128_absorb_block_script().compile().len();
+
+        // Each step on its own turns the previous traced state into the next
+        let mut state = [0u8; STACKSATSCRIPT_STATE_NIBBLES];
+        for (i, (step, after)) in steps.iter().zip(trace.iter()).enumerate() {
+            let script = script! {
+                { push_nibbles(&state) }
+                if i == 0 {
+                    { push_nibbles(&block) }
+                }
+                { step.clone() }
+                for nibble in after.iter().rev() {
+                    { *nibble as u32 }
+                    OP_EQUALVERIFY
+                }
+                OP_TRUE
+            };
+            assert!(
+                step.clone().compile().len() < block_size / 8,
+                "step {} is not small",
+                i
+            );
+            assert_eq!(run_script_checked(script.compile()), Ok(()), "step {}", i);
+            state = *after;
+        }
+
+        // Back to back they hash the message, like the block-sized step
+        for granularity in [StepGranularity::Block, StepGranularity::Round] {
+            let chained = script! {
+                { stacksat128_init_state_script() }
+                { push_nibbles(&block) }
+                for step in stacksat128_step_scripts(granularity) {
+                    { step }
+                }
+                { stacksat128_verify_output_script(digest) }
+            };
+            assert_eq!(
+                run_script_checked(chained.compile()),
+                Ok(()),
+                "{:?}",
+                granularity
+            );
+        }
+    }
+
+    #[test]
+    fn test_absorb_rate_block() {
+        let state: Vec<u8> = (0..STACKSATSCRIPT_STATE_NIBBLES)
+            .map(|i| (i * 7 + 3) as u8 & 0xF)
+            .collect();
+        let block: Vec<u8> = (0..STACKSATSCRIPT_RATE_NIBBLES)
+            .map(|i| (i * 5 + 11) as u8 & 0xF)
+            .collect();
+        // The rate nibbles take the block added mod 16, the capacity is untouched
+        let mut absorbed = state.clone();
+        for (nibble, m) in absorbed.iter_mut().zip(block.iter()) {
+            *nibble = (*nibble + m) & 0xF;
+        }
+
+        let script = script! {
+            for nibble in state.iter().chain(block.iter()) {
+                { *nibble as u32 }
+            }
+            { stacksat128_absorb_rate_block_script() }
+            for nibble in absorbed.iter().rev() {
+                { *nibble as u32 }
+                OP_EQUALVERIFY
+            }
+            OP_TRUE
+        };
+        assert_eq!(run_script_checked(script.compile()), Ok(()));
+    }
+
+    #[test]
+    fn test_message_len_cap() {
+        assert_eq!(supported_limb_sizes(), &[4]);
+
+        // The cap itself is accepted
+        stacksat128_compute_script_optimized(MAX_MESSAGE_BYTES);
+        stacksat128_push_message_script(&[0xAB; MAX_MESSAGE_BYTES]);
+
+        // One byte past it is rejected by every generator
+        let too_long = MAX_MESSAGE_BYTES + 1;
+        let rejects =
+            |generate: fn(usize)| std::panic::catch_unwind(|| generate(too_long)).is_err();
+        assert!(rejects(|n| drop(stacksat128_compute_script_optimized(n))));
+        assert!(rejects(|n| drop(stacksat128_compute_script_with_limb(n))));
+        assert!(rejects(|n| drop(stacksat128_compute_script_rounds(n, 16))));
+        assert!(rejects(|n| drop(stacksat128_script_asm(n))));
+        assert!(rejects(|n| {
+            stacksat128_push_message_script(&vec![0; n]);
+        }));
+        assert!(rejects(|n| {
+            let _ = stacksat128_push_message_script_from_reader(&vec![0; n][..]);
+        }));
+    }
+
+    #[test]
+    fn test_executable_message_limit() {
+        // The message nibbles are pushed directly, as a witness would supply
+        // them, so only the compute and verify scripts count against the limit
+        let run = |len: usize| {
+            let message: Vec<u8> = (0..len).map(|i| (i * 89 + 7) as u8).collect();
+            let script = script! {
+                for byte in message.iter() {
+                    { (*byte >> 4) as u32 }
+                    { (*byte & 0xF) as u32 }
+                }
+                { stacksat128_compute_script_optimized(len) }
+                { stacksat128_verify_output_script(stacksat128::stacksat_hash(&message)) }
+            };
+            let bytes = script.compile();
+            (
+                run_script_checked(bytes.clone()).is_ok(),
+                interpreter::run_checked(bytes.as_bytes()),
+            )
+        };
+
+        let (accepted, offline) = run(MAX_EXECUTABLE_MESSAGE_BYTES);
+        assert!(
+            accepted,
+            "{}-byte message rejected",
+            MAX_EXECUTABLE_MESSAGE_BYTES
+        );
+        assert_eq!(offline, Ok(()));
+
+        // The next length adds a block and overflows while absorbing it
+        let (accepted, offline) = run(MAX_EXECUTABLE_MESSAGE_BYTES + 1);
+        assert!(!accepted);
+        assert!(matches!(
+            offline,
+            Err(interpreter::ExecError::StackOverflow { .. })
+        ));
+    }
+
+    #[test]
+    fn test_oversize_policy() {
+        let message = vec![0x5A; MAX_MESSAGE_BYTES + 3];
+        let truncated = &message[..MAX_EXECUTABLE_MESSAGE_BYTES];
+        let compiled = |script: Result<Script, MessageTooLong>| script.unwrap().compile();
+
+        // Short messages are untouched by every policy
+        for policy in [
+            OversizePolicy::Panic,
+            OversizePolicy::Error,
+            OversizePolicy::Truncate,
+        ] {
+            let builder = ScriptBuilder::new().oversize_policy(policy);
+            assert_eq!(
+                compiled(builder.push_message_script(&message[..5])),
+                stacksat128_push_message_script(&message[..5]).compile()
+            );
+        }
+
+        let panics =
+            std::panic::catch_unwind(|| ScriptBuilder::new().compute_script(message.len()).is_ok());
+        assert!(panics.is_err());
+
+        let error = ScriptBuilder::new().oversize_policy(OversizePolicy::Error);
+        assert_eq!(
+            error.full_script(&message).unwrap_err(),
+            MessageTooLong { len: message.len() }
+        );
+        assert!(error.compute_script(MAX_MESSAGE_BYTES + 1).is_err());
+        assert!(error.compute_script(MAX_MESSAGE_BYTES).is_ok());
+        assert_eq!(
+            error.effective_len(message.len()),
+            Err(MessageTooLong { len: message.len() })
         );
 
-        if !result.success {
-            println!("This is expected during development - optimizations may need refinement");
-            println!("Error: {:?}", result.error);
+        // Truncation keeps an executable prefix, hashes and checks it, so the
+        // prefix itself passes
+        let truncate = ScriptBuilder::new().oversize_policy(OversizePolicy::Truncate);
+        assert_eq!(
+            truncate.effective_len(message.len()),
+            Ok(MAX_EXECUTABLE_MESSAGE_BYTES)
+        );
+        assert_eq!(
+            truncate.effective_len(MAX_EXECUTABLE_MESSAGE_BYTES + 1),
+            Ok(MAX_EXECUTABLE_MESSAGE_BYTES)
+        );
+        assert_eq!(truncate.effective_len(5), Ok(5));
+        assert_eq!(
+            compiled(truncate.compute_script(message.len())),
+            stacksat128_compute_script_optimized(MAX_EXECUTABLE_MESSAGE_BYTES).compile()
+        );
+        assert_eq!(
+            compiled(truncate.push_message_script(&message)),
+            stacksat128_push_message_script(truncated).compile()
+        );
+        assert_eq!(
+            compiled(truncate.full_script(&message)),
+            stacksat128_full_script(truncated).compile()
+        );
+
+        // The truncated script runs, given the prefix's nibbles as a witness
+        // would supply them (see test_executable_message_limit)
+        let script = script! {
+            for byte in truncated.iter() {
+                { (*byte >> 4) as u32 }
+                { (*byte & 0xF) as u32 }
+            }
+            { truncate.full_script(&message).unwrap() }
+        };
+        assert_eq!(run_script_checked(script.compile()), Ok(()));
+    }
+
+    #[test]
+    fn test_push_message_script_from_reader() {
+        // Hands out at most 5 bytes per read, interrupting every other call
+        struct Trickle<'a> {
+            data: &'a [u8],
+            interrupt: bool,
+        }
+        impl std::io::Read for Trickle<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                self.interrupt = !self.interrupt;
+                if self.interrupt {
+                    return Err(std::io::ErrorKind::Interrupted.into());
+                }
+                let n = buf.len().min(self.data.len()).min(5);
+                buf[..n].copy_from_slice(&self.data[..n]);
+                self.data = &self.data[n..];
+                Ok(n)
+            }
+        }
+
+        for len in [0usize, 5, 32, 43, 64, 100] {
+            let message: Vec<u8> = (0..len).map(|i| (i * 73 + 11) as u8).collect();
+            let expected = stacksat128_push_message_script(&message)
+                .compile()
+                .to_bytes();
+            let from_slice = stacksat128_push_message_script_from_reader(&message[..]).unwrap();
+            assert_eq!(from_slice.compile().to_bytes(), expected, "length {}", len);
+            let trickled = stacksat128_push_message_script_from_reader(Trickle {
+                data: &message,
+                interrupt: false,
+            })
+            .unwrap();
+            assert_eq!(trickled.compile().to_bytes(), expected, "length {}", len);
+        }
+
+        struct Failing;
+        impl std::io::Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::BrokenPipe.into())
+            }
+        }
+        let err = stacksat128_push_message_script_from_reader(Failing).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn test_verify_output_byte_order() {
+        let message = b"abc";
+        let digest = stacksat128::stacksat_hash(message);
+        let mut reversed = digest;
+        reversed.reverse();
+
+        let run = |expected: [u8; 32], order: ByteOrder| {
+            let script = script! {
+                { stacksat128_compute_with_message_script(message) }
+                { stacksat128_verify_output_script_with_order(expected, order) }
+            };
+            run_script_checked(script.compile())
+        };
+
+        assert_eq!(run(digest, ByteOrder::BigEndian), Ok(()));
+        assert_eq!(run(reversed, ByteOrder::LittleEndian), Ok(()));
+        assert!(run(reversed, ByteOrder::BigEndian).is_err());
+        assert!(run(digest, ByteOrder::LittleEndian).is_err());
+    }
+
+    #[test]
+    fn test_nibble_order() {
+        // Fixed reference layout for 0x12 0x34 0xAB, bottom of the stack first
+        let message = [0x12, 0x34, 0xAB];
+        let layouts = [
+            (NibbleOrder::MsbFirst, [0x1, 0x2, 0x3, 0x4, 0xA, 0xB]),
+            (NibbleOrder::LsbFirst, [0x2, 0x1, 0x4, 0x3, 0xB, 0xA]),
+        ];
+        let check_layout = |layout: &[u8]| {
+            script! {
+                for nibble in layout.iter().rev() {
+                    { *nibble as u32 }
+                    OP_EQUALVERIFY
+                }
+                OP_TRUE
+            }
+        };
+        for (order, layout) in layouts.iter() {
+            let pushed = script! {
+                { stacksat128_push_message_script_with_order(&message, *order) }
+                { check_layout(layout) }
+            };
+            assert_eq!(run_script_checked(pushed.compile()), Ok(()), "{:?}", order);
+
+            let reordered = script! {
+                { stacksat128_push_message_script_with_order(&message, *order) }
+                { stacksat128_reorder_nibbles_script(message.len(), *order) }
+                { check_layout(&layouts[0].1) }
+            };
+            assert_eq!(
+                run_script_checked(reordered.compile()),
+                Ok(()),
+                "{:?}",
+                order
+            );
+        }
+
+        // Either order hashes correctly once reordered, across block boundaries
+        for len in [0usize, 1, 16, 40] {
+            let message: Vec<u8> = (0..len).map(|i| (i * 71 + 9) as u8).collect();
+            let digest = stacksat128::stacksat_hash(&message);
+            for order in [NibbleOrder::MsbFirst, NibbleOrder::LsbFirst] {
+                let script = script! {
+                    { stacksat128_push_message_script_with_order(&message, order) }
+                    { stacksat128_reorder_nibbles_script(len, order) }
+                    { stacksat128_compute_script_optimized(len) }
+                    { stacksat128_verify_output_script(digest) }
+                };
+                assert_eq!(
+                    run_script_checked(script.compile()),
+                    Ok(()),
+                    "{:?}, length {}",
+                    order,
+                    len
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_transform_limbsize_nibble_order() {
+        // bitvm's 8-to-4-bit limb split must give the core crate's nibble
+        // order: byte i becomes nibbles 2i (high) and 2i + 1 (low), byte 0's
+        // high nibble deepest
+        let check_nibbles = |bytes: [u8; 32], nibbles: &[u8]| {
+            let script = script! {
+                for byte in bytes {
+                    { byte }
+                }
+                { U256::transform_limbsize(BYTE_LIMB_BITS, NIBBLE_LIMB_BITS) }
+                for nibble in nibbles.iter().rev() {
+                    { *nibble as u32 }
+                    OP_EQUALVERIFY
+                }
+                OP_TRUE
+            };
+            run_script_checked(script.compile())
+        };
+
+        let value: [u8; 32] = core::array::from_fn(|i| (i * 37 + 0x1F) as u8);
+        let split: Vec<u8> = value.iter().flat_map(|b| [b >> 4, b & 0xF]).collect();
+        assert_eq!(check_nibbles(value, &split), Ok(()));
+
+        // The digest splits into exactly the nibbles `stacksat_hash` packs
+        let digest = stacksat128::stacksat_hash(b"abc");
+        let nibbles = stacksat128::stacksat_hash_nibbles(b"abc");
+        assert_eq!(check_nibbles(digest, &nibbles), Ok(()));
+
+        // Swapping the halves of one byte is caught
+        let mut swapped = split.clone();
+        swapped.swap(0, 1);
+        assert!(check_nibbles(value, &swapped).is_err());
+    }
+
+    #[test]
+    fn test_preimage_check_script() {
+        // Three blocks: two full ones and an 8-byte tail
+        let message: Vec<u8> = (0..40u8).map(|i| i.wrapping_mul(37)).collect();
+        let digest = stacksat128::stacksat_hash(&message);
+        let check = stacksat128_preimage_check_script(message.len(), digest)
+            .compile()
+            .to_bytes();
+
+        let run = |preimage: &[u8]| {
+            let witness = script! {
+                for byte in preimage.iter() {
+                    { (byte >> 4) as u32 }
+                    { (byte & 0xF) as u32 }
+                }
+            };
+            let mut script_bytes = witness.compile().to_bytes();
+            script_bytes.extend_from_slice(&check);
+            run_script_checked(ScriptBuf::from_bytes(script_bytes))
+        };
+
+        assert_eq!(run(&message), Ok(()));
+        let mut wrong = message.clone();
+        wrong[20] ^= 0x10;
+        assert!(run(&wrong).is_err(), "wrong preimage accepted");
+    }
+
+    #[test]
+    fn test_preimage_and_sig_script() {
+        use bitcoin::secp256k1::{Keypair, Secp256k1, SecretKey};
+
+        let secp = Secp256k1::new();
+        let secret = SecretKey::from_slice(&[0x42; 32]).unwrap();
+        let (pubkey, _) = Keypair::from_secret_key(&secp, &secret).x_only_public_key();
+        let message = b"spend if you know me";
+        let digest = stacksat128::stacksat_hash(message);
+        let gadget = stacksat128_preimage_and_sig_script(message.len(), digest, &pubkey)
+            .compile()
+            .to_bytes();
+
+        // The signature check leads and the rest is the plain preimage check
+        let sig_check = script! {
+            { pubkey }
+            OP_CHECKSIGVERIFY
+        }
+        .compile()
+        .to_bytes();
+        assert_eq!(sig_check.len(), 34);
+        assert_eq!(&gadget[..sig_check.len()], &sig_check[..]);
+        let hash_check = &gadget[sig_check.len()..];
+        assert_eq!(
+            hash_check,
+            &stacksat128_preimage_check_script(message.len(), digest)
+                .compile()
+                .to_bytes()[..]
+        );
+
+        // The interpreter has no transaction to sign, so only the hash part runs
+        let run = |preimage: &[u8]| {
+            let witness = script! {
+                for byte in preimage.iter() {
+                    { (byte >> 4) as u32 }
+                    { (byte & 0xF) as u32 }
+                }
+            };
+            let mut script_bytes = witness.compile().to_bytes();
+            script_bytes.extend_from_slice(hash_check);
+            run_script_checked(ScriptBuf::from_bytes(script_bytes))
+        };
+        assert_eq!(run(message), Ok(()));
+        assert!(
+            run(b"spend if you know mE").is_err(),
+            "wrong preimage accepted"
+        );
+    }
+
+    #[test]
+    fn test_merkle_inclusion_script() {
+        use stacksat128::merkle::{merkle_proof, merkle_root, verify_proof};
+
+        // Depth-3 tree of 8 leaves
+        let leaves: Vec<[u8; 32]> = (0..8u8)
+            .map(|i| core::array::from_fn(|j| i.wrapping_mul(53) ^ (j as u8)))
+            .collect();
+        let root = merkle_root(&leaves);
+        let check = stacksat128_merkle_inclusion_script(root, 3)
+            .compile()
+            .to_bytes();
+
+        let run = |leaf: [u8; 32], index: usize, proof: &[[u8; 32]]| {
+            let mut script_bytes = stacksat128_merkle_witness_script(leaf, index, proof)
+                .compile()
+                .to_bytes();
+            script_bytes.extend_from_slice(&check);
+            run_script_checked(ScriptBuf::from_bytes(script_bytes))
+        };
+
+        // Leftmost and a mixed left/right path
+        for index in [0, 5] {
+            let proof = merkle_proof(&leaves, index);
+            assert!(verify_proof(&root, &leaves[index], index, &proof));
+            assert_eq!(run(leaves[index], index, &proof), Ok(()), "index {}", index);
+        }
+
+        let proof = merkle_proof(&leaves, 5);
+        let mut wrong_sibling = proof.clone();
+        wrong_sibling[1][7] ^= 0x01;
+        assert!(
+            run(leaves[5], 5, &wrong_sibling).is_err(),
+            "wrong sibling accepted"
+        );
+        assert!(
+            run(leaves[5], 4, &proof).is_err(),
+            "wrong direction bit accepted"
+        );
+        assert!(run(leaves[4], 5, &proof).is_err(), "wrong leaf accepted");
+    }
+
+    #[test]
+    fn test_digest_nibbles_in_range() {
+        // Consume the 64 elements the compute script leaves, each checked to
+        // be a number in 0..16 without comparing it to an expected digest
+        let nibbles_in_range = script! {
+            for _ in 0..STACKSATSCRIPT_STATE_NIBBLES {
+                0 16 OP_WITHIN OP_VERIFY
+            }
+            OP_TRUE
+        };
+
+        let mut seed = 0x9e37_79b9u32;
+        for len in [0usize, 1, 15, 32, 33, 64, 97] {
+            let message: Vec<u8> = (0..len)
+                .map(|_| {
+                    seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                    (seed >> 16) as u8
+                })
+                .collect();
+            let script = script! {
+                { stacksat128_push_message_script(&message) }
+                { stacksat128_compute_script_optimized(len) }
+                { nibbles_in_range.clone() }
+            };
+            assert_eq!(
+                run_script_checked(script.compile()),
+                Ok(()),
+                "{}-byte message",
+                len
+            );
+        }
+
+        // The check itself rejects an out-of-range element
+        let out_of_range = script! {
+            16
+            for _ in 1..STACKSATSCRIPT_STATE_NIBBLES {
+                0
+            }
+            { nibbles_in_range }
+        };
+        assert!(run_script_checked(out_of_range.compile()).is_err());
+    }
+
+    /// Main stack depth left by `script`, whether or not it succeeds.
+    fn final_stack_len(script: Script) -> usize {
+        execute_script_buf(script.compile()).final_stack.len()
+    }
+
+    #[test]
+    fn test_clean_stack() {
+        // Residue under a true result is rejected
+        assert_eq!(run_script_checked(script! { OP_TRUE }.compile()), Ok(()));
+        let leaky = run_script_checked(script! { OP_TRUE OP_TRUE }.compile());
+        assert_eq!(leaky.unwrap_err().error, None);
+
+        // The gadgets that leave the digest leave exactly its nibbles
+        let message = b"leave the digest";
+        let digest = stacksat128::stacksat_hash(message);
+        let leave_digest = script! {
+            { stacksat128_push_message_script(message) }
+            { stacksat128_compute_script_optimized(message.len()) }
+        };
+        assert_eq!(final_stack_len(leave_digest), 64);
+        assert_eq!(
+            final_stack_len(stacksat128_compute_with_message_script(message)),
+            64
+        );
+        let xof = script! {
+            { stacksat128_push_message_script(message) }
+            { stacksat128_xof_script(message.len(), 70) }
+        };
+        assert_eq!(final_stack_len(xof), 70);
+
+        // The verify gadgets consume them, which `run_script_checked` checks
+        let full = script! {
+            { stacksat128_push_message_script(message) }
+            { stacksat128_full_script(message) }
+        };
+        assert_eq!(run_script_checked(full.compile()), Ok(()));
+        let truncated = script! {
+            { stacksat128_push_message_script(message) }
+            { stacksat128_compute_script_optimized(message.len()) }
+            { stacksat128_verify_truncated_script(&digest, 4) }
+        };
+        assert_eq!(run_script_checked(truncated.compile()), Ok(()));
+        let mut reversed = digest;
+        reversed.reverse();
+        let little_endian = script! {
+            { stacksat128_push_message_script(message) }
+            { stacksat128_compute_script_optimized(message.len()) }
+            { stacksat128_verify_output_script_with_order(reversed, ByteOrder::LittleEndian) }
+        };
+        assert_eq!(run_script_checked(little_endian.compile()), Ok(()));
+    }
+
+    /// Block-aligned messages (16, 32 and 48 bytes) absorb 1, 2 and 3 blocks
+    /// with no padding block, matching the reference hash.
+    #[test]
+    fn test_block_multiple_lengths() {
+        for (len, blocks) in [(16usize, 1usize), (32, 2), (48, 3)] {
+            let message: Vec<u8> = (0..len).map(|i| (i * 13 + 5) as u8).collect();
+            let sections =
+                stacksat128_sections(len, STACKSATSCRIPT_ROUNDS, true, SboxStrategy::Table);
+            let absorbs = sections
+                .iter()
+                .filter(|(label, _)| label.starts_with("opt_absorb_"))
+                .count();
+            assert_eq!(absorbs, blocks, "length {}", len);
+            let padding = &sections[0];
+            assert_eq!(padding.0, "optimized_padding");
+            assert!(padding.1.clone().compile().to_bytes().is_empty());
+
+            let mut script_bytes = stacksat128_push_message_script(&message)
+                .compile()
+                .to_bytes();
+            script_bytes.extend(stacksat128_full_script(&message).compile().to_bytes());
+            if let Err(err) = run_script_checked(ScriptBuf::from_bytes(script_bytes)) {
+                panic!("script rejected {}-byte message: {}", len, err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_run_script_checked() {
+        let message = b"abc";
+        let expected_hash = stacksat128::stacksat_hash(message);
+        let mut wrong_hash = expected_hash;
+        wrong_hash[0] ^= 1;
+
+        let script_for = |expected: [u8; 32]| {
+            let mut script_bytes = stacksat128_push_message_script(message)
+                .compile()
+                .to_bytes();
+            script_bytes.extend(
+                stacksat128_compute_script_optimized(message.len())
+                    .compile()
+                    .to_bytes(),
+            );
+            script_bytes.extend(
+                stacksat128_verify_output_script(expected)
+                    .compile()
+                    .to_bytes(),
+            );
+            ScriptBuf::from_bytes(script_bytes)
+        };
+
+        assert_eq!(run_script_checked(script_for(expected_hash)), Ok(()));
+
+        let err = run_script_checked(script_for(wrong_hash)).unwrap_err();
+        assert!(err.error.is_some(), "mismatch must stop at OP_EQUALVERIFY");
+        assert!(err.last_opcode.is_some());
+        assert!(err.to_string().starts_with("script execution failed: "));
+    }
+
+    #[test]
+    fn test_errors_box_into_dyn_error() {
+        fn run(script: ScriptBuf) -> Result<(), Box<dyn std::error::Error>> {
+            run_script_checked(script)?;
+            Ok(())
+        }
+
+        assert!(run(script! { OP_TRUE }.compile()).is_ok());
+        for failing in [script! { OP_FALSE }, script! { OP_RETURN }] {
+            let err = run(failing.compile()).unwrap_err();
+            assert!(err.to_string().starts_with("script execution failed: "));
+            assert!(err.source().is_none());
+            assert!(err.downcast_ref::<ScriptExecError>().is_some());
         }
-        assert!(result.success, "Optimization correctness test failed");
     }
 }
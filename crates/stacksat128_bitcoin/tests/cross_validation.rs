@@ -0,0 +1,251 @@
+use bitcoin::ScriptBuf;
+use stacksat128::{padding, stacksat_hash, Stacksat128, TEST_VECTORS};
+use stacksat128_bitcoin::{
+    interpreter, reference_hash, run_script_checked, stacksat128_compute_script_optimized,
+    stacksat128_push_message_script, stacksat128_script_fragments,
+    stacksat128_verify_output_script, MAX_EXECUTABLE_MESSAGE_BYTES,
+};
+
+// Push + compute for `message`, leaving the 64 digest nibbles
+fn compute_script_bytes(message: &[u8]) -> Vec<u8> {
+    let mut script_bytes = stacksat128_push_message_script(message)
+        .compile()
+        .to_bytes();
+    script_bytes.extend(
+        stacksat128_compute_script_optimized(message.len())
+            .compile()
+            .to_bytes(),
+    );
+    script_bytes
+}
+
+// Push + compute + verify for `message` against `expected`
+fn check_script_bytes(message: &[u8], expected: [u8; 32]) -> Vec<u8> {
+    let mut script_bytes = compute_script_bytes(message);
+    script_bytes.extend(
+        stacksat128_verify_output_script(expected)
+            .compile()
+            .to_bytes(),
+    );
+    script_bytes
+}
+
+// Run push + compute + verify for `message` against the core crate's digest
+fn script_accepts(message: &[u8], expected: [u8; 32]) -> bool {
+    run_script_checked(ScriptBuf::from_bytes(check_script_bytes(message, expected))).is_ok()
+}
+
+// Digest nibbles left by the compute script, run by the offline interpreter
+fn interpreted_nibbles(message: &[u8]) -> Vec<u8> {
+    let stack = interpreter::execute(&compute_script_bytes(message))
+        .unwrap_or_else(|err| panic!("{}-byte message: {}", message.len(), err));
+    stack
+        .iter()
+        .map(|element| match element.as_slice() {
+            [] => 0,
+            [nibble] if *nibble < 16 => *nibble,
+            other => panic!("not a nibble: {:02x?}", other),
+        })
+        .collect()
+}
+
+// --- Committed Vectors ---
+
+#[test]
+fn test_script_matches_core_on_vectors() {
+    for (message, expected_hex) in TEST_VECTORS.iter() {
+        let digest = stacksat_hash(message);
+        assert_eq!(hex::encode(digest), *expected_hex);
+        assert!(
+            script_accepts(message, digest),
+            "script and core disagree on a {}-byte vector",
+            message.len()
+        );
+    }
+}
+
+// --- Uniform Inputs ---
+
+#[test]
+fn test_script_matches_core_on_uniform_inputs() {
+    // All-0xFF maximizes the carries in the script's mod-16 additions
+    for byte in [0x00u8, 0xFF] {
+        for len in [1usize, 15, 16, 17, 33, 64] {
+            let message = vec![byte; len];
+            assert!(
+                script_accepts(&message, stacksat_hash(&message)),
+                "script and core disagree on {:#04x} x {}",
+                byte,
+                len
+            );
+        }
+    }
+}
+
+// --- Pseudo-Random Inputs ---
+
+#[test]
+fn test_script_matches_core_on_random_inputs() {
+    let mut seed = 0x1234_5678u32;
+    let mut next_byte = || {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        (seed >> 16) as u8
+    };
+
+    // Partial, exact and multi-block lengths around the 16-byte rate
+    for len in [1usize, 7, 15, 16, 17, 31, 32, 33, 50, 64] {
+        let message: Vec<u8> = (0..len).map(|_| next_byte()).collect();
+        let digest = stacksat_hash(&message);
+        assert!(
+            script_accepts(&message, digest),
+            "script and core disagree on {:02x?}",
+            message
+        );
+
+        let mut wrong = digest;
+        wrong[31] ^= 1;
+        assert!(!script_accepts(&message, wrong), "length {}", len);
+    }
+}
+
+// --- Padding Boundaries ---
+
+#[test]
+fn test_script_padding_matches_core_at_block_boundaries() {
+    // Both sides zero-pad through `stacksat128::padding` (not the 10*1 padding
+    // of the specification), so they agree on the block count at and around
+    // every boundary, including the exact fit that gets no padding block
+    for len in [0usize, 1, 14, 15, 16, 17, 30, 31, 32, 33, 47, 48, 49] {
+        let message: Vec<u8> = (0..len).map(|i| (i * 29 + 3) as u8 | 1).collect();
+        let absorptions = stacksat128_script_fragments(len)
+            .iter()
+            .filter(|(label, _)| label.starts_with("opt_absorb_"))
+            .count();
+        let expected_blocks = if len == 0 {
+            // The empty message is served by its precomputed digest
+            0
+        } else {
+            padding::block_count(len)
+        };
+        assert_eq!(absorptions, expected_blocks, "length {}", len);
+        assert!(
+            script_accepts(&message, stacksat_hash(&message)),
+            "script and core disagree at length {}",
+            len
+        );
+
+        // Zero padding is not injective, on either side: a trailing zero byte
+        // that stays within the last block hashes alike
+        if len % 16 != 0 {
+            let mut extended = message.clone();
+            extended.push(0);
+            assert_eq!(stacksat_hash(&extended), stacksat_hash(&message));
+            assert!(script_accepts(&extended, stacksat_hash(&message)));
+        }
+    }
+}
+
+// --- Regression Corpus ---
+
+// Each file in `tests/regressions` is one message, raw bytes. Inputs found by
+// fuzzing go there so they stay checked; the file name is only a label.
+const REGRESSIONS_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/regressions");
+
+#[test]
+fn test_regression_corpus() {
+    let mut paths: Vec<_> = std::fs::read_dir(REGRESSIONS_DIR)
+        .expect("regression corpus directory")
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "empty regression corpus");
+
+    for path in paths {
+        let message = std::fs::read(&path).unwrap();
+        let name = path.file_name().unwrap().to_string_lossy();
+        assert!(
+            message.len() <= MAX_EXECUTABLE_MESSAGE_BYTES,
+            "{}: {} bytes, over the {}-byte cap",
+            name,
+            message.len(),
+            MAX_EXECUTABLE_MESSAGE_BYTES
+        );
+
+        let digest = stacksat_hash(&message);
+        let mut streamed = Stacksat128::new();
+        for byte in message.iter() {
+            streamed.update(core::slice::from_ref(byte));
+        }
+        assert_eq!(streamed.finalize(), digest, "{}: streaming", name);
+        assert_eq!(reference_hash(&message), digest, "{}: reference", name);
+        assert!(script_accepts(&message, digest), "{}: script", name);
+    }
+}
+
+// --- Offline Interpreter ---
+
+#[test]
+fn test_interpreter_matches_bitvm() {
+    let mut messages: Vec<Vec<u8>> = TEST_VECTORS.iter().map(|(m, _)| m.to_vec()).collect();
+    messages.push(vec![0xFF; 33]);
+    for message in messages {
+        let digest = stacksat_hash(&message);
+        let mut wrong = digest;
+        wrong[0] ^= 0x10;
+        for (expected, accepted) in [(digest, true), (wrong, false)] {
+            let script_bytes = check_script_bytes(&message, expected);
+            assert_eq!(
+                interpreter::run_checked(&script_bytes).is_ok(),
+                accepted,
+                "interpreter, {}-byte message",
+                message.len()
+            );
+            assert_eq!(
+                run_script_checked(ScriptBuf::from_bytes(script_bytes)).is_ok(),
+                accepted,
+                "bitvm, {}-byte message",
+                message.len()
+            );
+        }
+        assert_eq!(
+            interpreted_nibbles(&message),
+            stacksat128::stacksat_hash_nibbles(&message)
+        );
+    }
+}
+
+#[test]
+fn test_interpreter_every_short_length() {
+    // Fast enough to run without `slow_tests`: the digest left on the stack
+    // against the core nibbles for every length up to four blocks
+    for len in 0..=64usize {
+        let message: Vec<u8> = (0..len).map(|i| (i * 151 + len) as u8).collect();
+        assert_eq!(
+            interpreted_nibbles(&message),
+            stacksat128::stacksat_hash_nibbles(&message),
+            "length {}",
+            len
+        );
+    }
+}
+
+// --- Exhaustive Lengths ---
+
+#[test]
+#[cfg(feature = "slow_tests")]
+fn test_script_matches_core_on_every_length() {
+    // Covers empty, single-block, exact-boundary and multi-block messages;
+    // collects every failing length instead of stopping at the first one.
+    // Run with `cargo test -p stacksat128_bitcoin --release --features slow_tests`
+    let failures: Vec<usize> = (0..=200usize)
+        .filter(|&len| {
+            let message: Vec<u8> = (0..len).map(|i| (i * 151 + len) as u8).collect();
+            !script_accepts(&message, stacksat_hash(&message))
+        })
+        .collect();
+    assert!(
+        failures.is_empty(),
+        "script and core disagree at lengths {:?}",
+        failures
+    );
+}
@@ -1,17 +1,15 @@
-use stacksat128::stacksat_hash;
+use stacksat128::{stacksat_hash, stacksat_hash_str_hex};
 
 fn main() {
     let input = "Hello, world!";
-    let hash = stacksat_hash(input.as_bytes());
     println!("Input: {}", input);
     println!("Input hex: {}", hex::encode(input.as_bytes()));
-    println!("Hash: {}", hex::encode(hash));
+    println!("Hash: {}", stacksat_hash_str_hex(input));
 
     let input2 = "Hello, world";
-    let hash2 = stacksat_hash(input2.as_bytes());
     println!("Input 2: {}", input2);
     println!("Input 2 hex: {}", hex::encode(input2.as_bytes()));
-    println!("Hash 2: {}", hex::encode(hash2));
+    println!("Hash 2: {}", stacksat_hash_str_hex(input2));
 
     let input3 = [0x00; 32].as_slice();
     let hash3 = stacksat_hash(input3);